@@ -0,0 +1,1896 @@
+use base64::{engine::general_purpose, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::Cursor;
+
+// PSNR is unbounded for identical images; clamp so the value stays valid JSON.
+const MAX_PSNR_DB: f64 = 100.0;
+// Sharpness is measured on a copy no larger than this to keep it fast and
+// comparable across source resolutions.
+const QUALITY_SAMPLE_DIM: u32 = 512;
+const DEFAULT_BLUR_THRESHOLD: f64 = 100.0;
+const MIN_SOLVED_MAX_DIM: u32 = 16;
+const MAX_DIM_SEARCH_STEPS: u32 = 10;
+const DEFAULT_MAX_FILE_BYTES: u64 = 25 * 1024 * 1024;
+const DOCUMENT_SAMPLE_DIM: u32 = 256;
+const MAX_PALETTE_COLORS: usize = 256;
+// NeuQuant sampling factor: 1 is slowest/best, 30 fastest. Icons are small.
+const QUANTIZE_SAMPLE_FACTOR: i32 = 10;
+const COLOR_SAMPLE_DIM: u32 = 64;
+const KMEANS_ITERATIONS: usize = 10;
+const MAX_BENCHMARK_KB: u32 = 64 * 1024;
+const DERIVED_JPEG_QUALITY: u8 = 90;
+// A scaled overlay may be at most this many times the base's width/height.
+const MAX_OVERLAY_SCALE_RATIO: f64 = 2.0;
+const ADAPTIVE_WINDOW_RADIUS: u32 = 15;
+const DEFAULT_ADAPTIVE_OFFSET: u8 = 10;
+const MAX_COMPOSED_PIXELS: u64 = 64 * 1024 * 1024;
+const PREVIEW_MAX_DIM: u32 = 1024;
+const EMPTY_IMAGE_DATA: &str = "Bad input: empty image data";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImageSimilarity {
+    ssim: f64,
+    psnr: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImagePlaceholder {
+    placeholder: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrimResult {
+    data_url: String,
+    width: u32,
+    height: u32,
+    blank: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImageQuality {
+    sharpness: f64,
+    brightness: f64,
+    is_blurry: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DocumentScore {
+    score: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MaxDimSolution {
+    max_dim: u32,
+    resulting_bytes: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DataUrlInfo {
+    mime_type: String,
+    bytes: usize,
+    is_image: bool,
+    detected_format: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DominantColor {
+    rgb: [u8; 3],
+    fraction: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Base64Benchmark {
+    decode_mbps: f64,
+    encode_mbps: f64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct PayloadCheck {
+    safe: bool,
+    detected: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileDimensions {
+    width: u32,
+    height: u32,
+    format: Option<String>,
+    ico_frame: Option<Dimensions>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Dimensions {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImageProbe {
+    path: String,
+    format: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    bytes: Option<u64>,
+    color_type: Option<String>,
+    bit_depth: Option<u16>,
+    ico_frame: Option<Dimensions>,
+    error: Option<String>,
+}
+
+/// Splits a `data:<mime>;base64,<data>` URL into its mime type and decoded bytes.
+/// Bare base64 without a header is accepted with an empty mime type. Empty or
+/// whitespace-only input is rejected rather than decoded to zero bytes.
+pub(crate) fn parse_data_url(data_url: &str) -> Result<(String, Vec<u8>), String> {
+    let trimmed = data_url.trim();
+    if trimmed.is_empty() {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+    let (header, data) = match trimmed.strip_prefix("data:") {
+        Some(rest) => rest
+            .split_once(',')
+            .ok_or_else(|| "Malformed data URL: missing ','".to_string())?,
+        None => ("", trimmed),
+    };
+    if !header.is_empty() && !header.ends_with(";base64") {
+        return Err("Malformed data URL: expected base64 encoding".to_string());
+    }
+    let mime_type = header.trim_end_matches(";base64").to_string();
+    if data.trim().is_empty() {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+    let bytes = general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| e.to_string())?;
+    Ok((mime_type, bytes))
+}
+
+pub(crate) fn build_data_url(mime_type: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+const ICO_HEADER_LEN: usize = 6;
+const ICO_ENTRY_LEN: usize = 16;
+
+// The ico decoder prefers colour depth over size, so a 32bpp 16x16 entry can
+// win over a 256x256 one. Rewrite the directory to hold only the largest
+// entry; offsets are absolute, so they shift by the dropped entries' bytes.
+// Also returns the chosen entry's width and height.
+fn largest_ico_frame(bytes: &[u8]) -> Option<(Vec<u8>, (u32, u32))> {
+    if image::guess_format(bytes).ok()? != ImageFormat::Ico {
+        return None;
+    }
+    let count = u16::from_le_bytes([*bytes.get(4)?, *bytes.get(5)?]) as usize;
+    let dir_end = ICO_HEADER_LEN + count * ICO_ENTRY_LEN;
+    if count < 2 || bytes.len() < dir_end {
+        return None;
+    }
+    let side = |b: u8| if b == 0 { 256 } else { b as u32 };
+    let best = (0..count).max_by_key(|&i| {
+        let entry = &bytes[ICO_HEADER_LEN + i * ICO_ENTRY_LEN..];
+        side(entry[0]) * side(entry[1])
+    })?;
+    let mut entry = bytes[ICO_HEADER_LEN + best * ICO_ENTRY_LEN..][..ICO_ENTRY_LEN].to_vec();
+    let dimensions = (side(entry[0]), side(entry[1]));
+    let offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+    let shift = ((count - 1) * ICO_ENTRY_LEN) as u32;
+    entry[12..16].copy_from_slice(&offset.checked_sub(shift)?.to_le_bytes());
+
+    let mut out = Vec::with_capacity(bytes.len() - shift as usize);
+    out.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    out.extend_from_slice(&entry);
+    out.extend_from_slice(&bytes[dir_end..]);
+    Some((out, dimensions))
+}
+
+// Size of the ICO frame decode_image picks, reported alongside the header
+// dimensions (which come from whichever frame the ico decoder prefers).
+fn ico_frame_dimensions(path: &str, format: Option<ImageFormat>) -> Option<Dimensions> {
+    if format != Some(ImageFormat::Ico) {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    let (_, (width, height)) = largest_ico_frame(&bytes)?;
+    Some(Dimensions { width, height })
+}
+
+// Decodes image bytes, turning a decoder panic on corrupt input into an error
+// instead of taking down the command worker.
+fn decode_image(bytes: &[u8]) -> Result<DynamicImage, String> {
+    if let Some((frame, _)) = largest_ico_frame(bytes) {
+        if let Ok(Ok(image)) = std::panic::catch_unwind(|| image::load_from_memory(&frame)) {
+            return Ok(image);
+        }
+    }
+    std::panic::catch_unwind(|| image::load_from_memory(bytes))
+        .map_err(|_| "Decode error: image data is corrupt".to_string())?
+        .map_err(|e| format!("Decode error: {}", e))
+}
+
+pub(crate) fn decode_data_url(data_url: &str) -> Result<DynamicImage, String> {
+    let (_, bytes) = parse_data_url(data_url)?;
+    decode_image(&bytes)
+}
+
+fn psnr(a: &DynamicImage, b: &DynamicImage) -> f64 {
+    let a = a.to_rgb8();
+    let b = b.to_rgb8();
+    let samples = a.as_raw().len();
+    if samples == 0 {
+        return MAX_PSNR_DB;
+    }
+    let sum_sq: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(x, y)| {
+            let d = *x as f64 - *y as f64;
+            d * d
+        })
+        .sum();
+    let mse = sum_sq / samples as f64;
+    if mse == 0.0 {
+        return MAX_PSNR_DB;
+    }
+    (10.0 * (255.0 * 255.0 / mse).log10()).min(MAX_PSNR_DB)
+}
+
+/// Compares two images, resizing the smaller one to match the larger first.
+/// SSIM is computed on luma; PSNR is computed over RGB and capped at 100 dB.
+#[tauri::command(async)]
+pub(crate) fn compare_images(
+    a_data_url: String,
+    b_data_url: String,
+) -> Result<ImageSimilarity, String> {
+    let mut a = decode_data_url(&a_data_url)?;
+    let mut b = decode_data_url(&b_data_url)?;
+    if a.width() == 0 || a.height() == 0 || b.width() == 0 || b.height() == 0 {
+        return Err("Cannot compare an empty image".to_string());
+    }
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        let a_area = a.width() as u64 * a.height() as u64;
+        let b_area = b.width() as u64 * b.height() as u64;
+        if a_area < b_area {
+            a = a.resize_exact(b.width(), b.height(), FilterType::Lanczos3);
+        } else {
+            b = b.resize_exact(a.width(), a.height(), FilterType::Lanczos3);
+        }
+    }
+
+    let ssim = image_compare::gray_similarity_structure(
+        &image_compare::Algorithm::MSSIMSimple,
+        &a.to_luma8(),
+        &b.to_luma8(),
+    )
+    .map_err(|e| e.to_string())?
+    .score;
+
+    Ok(ImageSimilarity {
+        ssim,
+        psnr: psnr(&a, &b),
+    })
+}
+
+fn exif_ascii(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    match &field.value {
+        exif::Value::Ascii(parts) => parts
+            .first()
+            .map(|raw| String::from_utf8_lossy(raw).trim().to_string())
+            .filter(|text| !text.is_empty()),
+        _ => None,
+    }
+}
+
+fn exif_gps_coordinate(exif: &exif::Exif, tag: exif::Tag, ref_tag: exif::Tag) -> Option<f64> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let parts = match &field.value {
+        exif::Value::Rational(parts) if parts.len() == 3 => parts,
+        _ => return None,
+    };
+    let degrees = parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0;
+    if !degrees.is_finite() {
+        return None;
+    }
+    match exif_ascii(exif, ref_tag).as_deref() {
+        Some("S") | Some("W") => Some(-degrees),
+        _ => Some(degrees),
+    }
+}
+
+/// Returns the audit-relevant EXIF tags as a flat JSON object. GPS
+/// coordinates are converted to signed decimal degrees. Images without
+/// EXIF, including formats that can't carry it (GIF, BMP), yield an empty
+/// object.
+#[tauri::command(async)]
+pub(crate) fn read_exif(data_url: String) -> Result<serde_json::Value, String> {
+    let (_, bytes) = parse_data_url(&data_url)?;
+    let mut map = serde_json::Map::new();
+    let exif = match exif::Reader::new().read_from_container(&mut Cursor::new(bytes)) {
+        Ok(exif) => exif,
+        Err(exif::Error::NotFound(_))
+        | Err(exif::Error::BlankValue(_))
+        | Err(exif::Error::InvalidFormat(_)) => {
+            return Ok(serde_json::Value::Object(map));
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if let Some(value) = exif_ascii(&exif, exif::Tag::DateTimeOriginal) {
+        map.insert("dateTimeOriginal".to_string(), value.into());
+    }
+    if let Some(value) =
+        exif_gps_coordinate(&exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef)
+    {
+        map.insert("gpsLatitude".to_string(), value.into());
+    }
+    if let Some(value) =
+        exif_gps_coordinate(&exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef)
+    {
+        map.insert("gpsLongitude".to_string(), value.into());
+    }
+    if let Some(value) = exif_ascii(&exif, exif::Tag::Make) {
+        map.insert("make".to_string(), value.into());
+    }
+    if let Some(value) = exif_ascii(&exif, exif::Tag::Model) {
+        map.insert("model".to_string(), value.into());
+    }
+    if let Some(value) = exif
+        .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+    {
+        map.insert("orientation".to_string(), value.into());
+    }
+    Ok(serde_json::Value::Object(map))
+}
+
+fn probe_image_file(path: &str) -> Result<ImageProbe, String> {
+    let bytes = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let reader = ImageReader::open(path)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let ico_frame = ico_frame_dimensions(path, reader.format());
+    let format = reader
+        .format()
+        .map(|format| format!("{:?}", format).to_lowercase());
+    // The decoder reads the header only; pixels are never decoded here.
+    let decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    Ok(ImageProbe {
+        path: path.to_string(),
+        format,
+        width: Some(width),
+        height: Some(height),
+        bytes: Some(bytes),
+        color_type: Some(format!("{:?}", color)),
+        bit_depth: Some(color.bits_per_pixel() / color.channel_count() as u16),
+        ico_frame,
+        error: None,
+    })
+}
+
+/// Reads format, dimensions and file size for each path from the image
+/// header only. Failures are reported per entry so one bad file does not
+/// fail the whole import preview.
+#[tauri::command(async)]
+pub(crate) fn probe_images(paths: Vec<String>) -> Vec<ImageProbe> {
+    paths
+        .par_iter()
+        .map(|path| {
+            probe_image_file(path).unwrap_or_else(|error| ImageProbe {
+                path: path.clone(),
+                error: Some(error),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const EXIF_ORIENTATION_TAG: u16 = 0x0112;
+
+// EXIF orientations expressed as (mirror first, then clockwise quarter turns).
+const ORIENTATIONS: [(bool, u8); 8] = [
+    (false, 0),
+    (true, 0),
+    (false, 2),
+    (true, 2),
+    (true, 3),
+    (false, 1),
+    (true, 1),
+    (false, 3),
+];
+
+fn rotate_orientation(orientation: u16, quarter_turns: u8) -> u16 {
+    let index = (orientation.clamp(1, 8) - 1) as usize;
+    let (mirrored, turns) = ORIENTATIONS[index];
+    let target = (mirrored, (turns + quarter_turns) % 4);
+    ORIENTATIONS
+        .iter()
+        .position(|entry| *entry == target)
+        .map(|i| i as u16 + 1)
+        .unwrap_or(1)
+}
+
+// Returns the byte range of the APP1 Exif segment payload (after the length
+// field) and the offset where a new APP1 segment should be inserted.
+fn find_jpeg_exif(bytes: &[u8]) -> Result<(Option<(usize, usize)>, usize), String> {
+    if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] != 0xD8 {
+        return Err("Lossless rotation only supports JPEG input".to_string());
+    }
+    let mut pos = 2;
+    let mut insert_at = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let start = pos + 4;
+        let end = pos + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return Err("Malformed JPEG segment".to_string());
+        }
+        if marker == 0xE1 && bytes[start..end].starts_with(EXIF_HEADER) {
+            return Ok((Some((start, end)), insert_at));
+        }
+        if marker == 0xE0 {
+            insert_at = end;
+        }
+        pos = end;
+    }
+    Ok((None, insert_at))
+}
+
+// Overwrites the orientation value inside IFD0 of a TIFF block in place.
+// Returns false when IFD0 has no orientation entry to patch.
+fn patch_tiff_orientation(tiff: &mut [u8], orientation: u16) -> bool {
+    if tiff.len() < 8 {
+        return false;
+    }
+    let little = match &tiff[..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return false,
+    };
+    let read_u16 = |b: &[u8]| {
+        if little {
+            u16::from_le_bytes([b[0], b[1]])
+        } else {
+            u16::from_be_bytes([b[0], b[1]])
+        }
+    };
+    let ifd = if little {
+        u32::from_le_bytes([tiff[4], tiff[5], tiff[6], tiff[7]])
+    } else {
+        u32::from_be_bytes([tiff[4], tiff[5], tiff[6], tiff[7]])
+    } as usize;
+    if ifd + 2 > tiff.len() {
+        return false;
+    }
+    let count = read_u16(&tiff[ifd..]) as usize;
+    for i in 0..count {
+        let entry = ifd + 2 + i * 12;
+        if entry + 12 > tiff.len() {
+            return false;
+        }
+        if read_u16(&tiff[entry..]) == EXIF_ORIENTATION_TAG && read_u16(&tiff[entry + 2..]) == 3 {
+            let value = if little {
+                orientation.to_le_bytes()
+            } else {
+                orientation.to_be_bytes()
+            };
+            tiff[entry + 8..entry + 10].copy_from_slice(&value);
+            return true;
+        }
+    }
+    false
+}
+
+fn build_exif_segment(existing: Option<&exif::Exif>, orientation: u16) -> Result<Vec<u8>, String> {
+    let orientation_field = exif::Field {
+        tag: exif::Tag::Orientation,
+        ifd_num: exif::In::PRIMARY,
+        value: exif::Value::Short(vec![orientation]),
+    };
+    let mut writer = exif::experimental::Writer::new();
+    writer.push_field(&orientation_field);
+    let mut little_endian = false;
+    if let Some(existing) = existing {
+        little_endian = existing.little_endian();
+        for field in existing.fields() {
+            if field.ifd_num == exif::In::PRIMARY && field.tag != exif::Tag::Orientation {
+                writer.push_field(field);
+            }
+        }
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    writer
+        .write(&mut tiff, little_endian)
+        .map_err(|e| e.to_string())?;
+    let tiff = tiff.into_inner();
+
+    let len = EXIF_HEADER.len() + tiff.len() + 2;
+    let len = u16::try_from(len).map_err(|_| "EXIF block too large".to_string())?;
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(EXIF_HEADER);
+    segment.extend_from_slice(&tiff);
+    Ok(segment)
+}
+
+/// Rotates a JPEG by a multiple of 90 degrees clockwise by rewriting its EXIF
+/// orientation tag; the compressed pixel data is returned untouched. When the
+/// image has EXIF but no orientation entry, the primary IFD is re-written
+/// with one added and any embedded thumbnail is dropped.
+#[tauri::command(async)]
+pub(crate) fn rotate_jpeg_lossless(data_url: String, degrees: i32) -> Result<String, String> {
+    if degrees % 90 != 0 {
+        return Err("Rotation must be a multiple of 90 degrees".to_string());
+    }
+    let quarter_turns = (degrees / 90).rem_euclid(4) as u8;
+    let (_, mut bytes) = parse_data_url(&data_url)?;
+    let (segment, insert_at) = find_jpeg_exif(&bytes)?;
+
+    let existing = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(&bytes))
+        .ok();
+    let current = existing
+        .as_ref()
+        .and_then(|exif| exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY))
+        .and_then(|field| field.value.get_uint(0))
+        .map(|value| value as u16)
+        .unwrap_or(1);
+    let orientation = rotate_orientation(current, quarter_turns);
+
+    match segment {
+        Some((start, end)) => {
+            let tiff_start = start + EXIF_HEADER.len();
+            if !patch_tiff_orientation(&mut bytes[tiff_start..end], orientation) {
+                let replacement = build_exif_segment(existing.as_ref(), orientation)?;
+                bytes.splice(start - 4..end, replacement);
+            }
+        }
+        None => {
+            let segment = build_exif_segment(None, orientation)?;
+            bytes.splice(insert_at..insert_at, segment);
+        }
+    }
+    Ok(build_data_url("image/jpeg", &bytes))
+}
+
+/// Lays several images out on one canvas for archiving, e.g. the front and
+/// back of a receipt. "horizontal" scales every image to the smallest
+/// height and "vertical" to the smallest width; "grid" fits each image into
+/// a near-square grid of cells the size of the smallest width and height.
+/// Images are separated and framed by `gap` pixels of `bg`. Returns a JPEG.
+#[tauri::command(async)]
+pub(crate) fn compose_images(
+    data_urls: Vec<String>,
+    layout: String,
+    gap: u32,
+    bg: [u8; 3],
+) -> Result<String, String> {
+    if data_urls.is_empty() {
+        return Err("Bad input: no images to compose".to_string());
+    }
+    let images = data_urls
+        .par_iter()
+        .map(|data_url| decode_data_url(data_url))
+        .collect::<Result<Vec<_>, _>>()?;
+    let min_width = images.iter().map(|image| image.width()).min().unwrap_or(0);
+    let min_height = images.iter().map(|image| image.height()).min().unwrap_or(0);
+    if min_width == 0 || min_height == 0 {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+    let gap = gap as u64;
+    // Scales (width, height) by num/den, never rounding a side down to zero.
+    let scale = |side: u32, num: u32, den: u32| {
+        ((side as u64 * num as u64 + den as u64 / 2) / den as u64).max(1) as u32
+    };
+
+    // Each placement is (x, y, width, height) on the canvas.
+    let mut placements = Vec::with_capacity(images.len());
+    let (canvas_w, canvas_h) = match layout.as_str() {
+        "horizontal" => {
+            let mut x = gap;
+            for image in &images {
+                let width = scale(image.width(), min_height, image.height());
+                placements.push((x, gap, width, min_height));
+                x += width as u64 + gap;
+            }
+            (x, min_height as u64 + 2 * gap)
+        }
+        "vertical" => {
+            let mut y = gap;
+            for image in &images {
+                let height = scale(image.height(), min_width, image.width());
+                placements.push((gap, y, min_width, height));
+                y += height as u64 + gap;
+            }
+            (min_width as u64 + 2 * gap, y)
+        }
+        "grid" => {
+            let cols = (images.len() as f64).sqrt().ceil() as u64;
+            let rows = (images.len() as u64).div_ceil(cols);
+            let (cell_w, cell_h) = (min_width as u64, min_height as u64);
+            for (i, image) in images.iter().enumerate() {
+                let (col, row) = (i as u64 % cols, i as u64 / cols);
+                // Fit inside the cell, matching whichever side is tighter.
+                let (width, height) =
+                    if cell_w * image.height() as u64 <= cell_h * image.width() as u64 {
+                        (min_width, scale(image.height(), min_width, image.width()))
+                    } else {
+                        (scale(image.width(), min_height, image.height()), min_height)
+                    };
+                let x = gap + col * (cell_w + gap) + (cell_w - width as u64) / 2;
+                let y = gap + row * (cell_h + gap) + (cell_h - height as u64) / 2;
+                placements.push((x, y, width, height));
+            }
+            (gap + cols * (cell_w + gap), gap + rows * (cell_h + gap))
+        }
+        other => return Err(format!("Bad input: unknown layout {}", other)),
+    };
+    if canvas_w.saturating_mul(canvas_h) > MAX_COMPOSED_PIXELS {
+        return Err(format!(
+            "Bad input: a {}x{} composition is too large",
+            canvas_w, canvas_h
+        ));
+    }
+
+    let mut canvas = image::RgbaImage::from_pixel(
+        canvas_w as u32,
+        canvas_h as u32,
+        image::Rgba([bg[0], bg[1], bg[2], 255]),
+    );
+    for (image, (x, y, width, height)) in images.iter().zip(placements) {
+        let scaled = if (width, height) == (image.width(), image.height()) {
+            image.to_rgba8()
+        } else {
+            image
+                .resize_exact(width, height, FilterType::Lanczos3)
+                .to_rgba8()
+        };
+        image::imageops::overlay(&mut canvas, &scaled, x as i64, y as i64);
+    }
+    encode_keeping_alpha(&DynamicImage::ImageRgba8(canvas), false)
+}
+
+/// Resizes to a `width` x `height` target. "fit" (default) scales down to
+/// fit inside the box keeping the aspect ratio and never upscales; "fill"
+/// scales to cover the box and centre-crops to exactly that size, for
+/// uniform thumbnail grids; "exact" stretches to the box. Returns a PNG when
+/// the source has transparency, otherwise a JPEG.
+#[tauri::command(async)]
+pub(crate) fn resize_image(
+    data_url: String,
+    width: u32,
+    height: u32,
+    mode: Option<String>,
+) -> Result<String, String> {
+    if width == 0 || height == 0 {
+        return Err("Bad input: target width and height must be non-zero".to_string());
+    }
+    let image = decode_data_url(&data_url)?;
+    let (src_w, src_h) = (image.width(), image.height());
+    if src_w == 0 || src_h == 0 {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+    let has_alpha = image.color().has_alpha();
+    let scale_x = width as f64 / src_w as f64;
+    let scale_y = height as f64 / src_h as f64;
+    let scaled = |scale: f64| {
+        (
+            ((src_w as f64 * scale).round() as u32).max(1),
+            ((src_h as f64 * scale).round() as u32).max(1),
+        )
+    };
+    let resized = match mode.as_deref().unwrap_or("fit") {
+        "fit" => {
+            let (out_w, out_h) = scaled(scale_x.min(scale_y).min(1.0));
+            if (out_w, out_h) == (src_w, src_h) {
+                image
+            } else {
+                image.resize_exact(out_w, out_h, FilterType::Lanczos3)
+            }
+        }
+        "fill" => {
+            let (cover_w, cover_h) = scaled(scale_x.max(scale_y));
+            let (cover_w, cover_h) = (cover_w.max(width), cover_h.max(height));
+            image
+                .resize_exact(cover_w, cover_h, FilterType::Lanczos3)
+                .crop_imm((cover_w - width) / 2, (cover_h - height) / 2, width, height)
+        }
+        "exact" => image.resize_exact(width, height, FilterType::Lanczos3),
+        other => return Err(format!("Unknown resize mode: {}", other)),
+    };
+    encode_keeping_alpha(&resized, has_alpha)
+}
+
+/// Generates a compact blurhash or thumbhash (base64) placeholder from a
+/// downscaled copy of the image. Width and height are the source dimensions
+/// so the frontend can reserve the right aspect ratio.
+#[tauri::command(async)]
+pub(crate) fn generate_placeholder(
+    data_url: String,
+    kind: String,
+) -> Result<ImagePlaceholder, String> {
+    let image = decode_data_url(&data_url)?;
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Err("Cannot generate a placeholder for an empty image".to_string());
+    }
+    let placeholder = match kind.as_str() {
+        "blurhash" => {
+            let small = image.thumbnail(32, 32).to_rgba8();
+            let (components_x, components_y) = if width >= height { (4, 3) } else { (3, 4) };
+            blurhash::encode(
+                components_x,
+                components_y,
+                small.width(),
+                small.height(),
+                small.as_raw(),
+            )
+            .map_err(|e| e.to_string())?
+        }
+        "thumbhash" => {
+            let small = image.thumbnail(100, 100).to_rgba8();
+            let hash = thumbhash::rgba_to_thumb_hash(
+                small.width() as usize,
+                small.height() as usize,
+                small.as_raw(),
+            );
+            general_purpose::STANDARD.encode(hash)
+        }
+        other => return Err(format!("Unknown placeholder kind: {}", other)),
+    };
+    Ok(ImagePlaceholder {
+        placeholder,
+        width,
+        height,
+    })
+}
+
+/// Crops uniform margins off a scan. The background is the colour most of
+/// the four corners agree on (ties go to the top-left), and a pixel counts
+/// as background when every channel is within `tolerance` of it. An image
+/// with no margin is returned unchanged; so is a blank one, with `blank` set.
+#[tauri::command(async)]
+pub(crate) fn auto_trim(data_url: String, tolerance: u8) -> Result<TrimResult, String> {
+    let image = decode_data_url(&data_url)?;
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+    let rgba = image.to_rgba8();
+    let near = |a: &image::Rgba<u8>, b: &image::Rgba<u8>| {
+        a.0.iter()
+            .zip(b.0.iter())
+            .all(|(x, y)| x.abs_diff(*y) <= tolerance)
+    };
+    let corners = [
+        rgba.get_pixel(0, 0),
+        rgba.get_pixel(width - 1, 0),
+        rgba.get_pixel(0, height - 1),
+        rgba.get_pixel(width - 1, height - 1),
+    ];
+    // max_by_key keeps the last maximum, so search from the bottom-right.
+    let background = corners
+        .iter()
+        .rev()
+        .max_by_key(|corner| corners.iter().filter(|other| near(corner, other)).count())
+        .copied()
+        .unwrap_or(corners[0]);
+
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        if near(pixel, background) {
+            continue;
+        }
+        bounds = Some(match bounds {
+            None => (x, y, x, y),
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        });
+    }
+    let unchanged = |blank: bool| TrimResult {
+        data_url: data_url.clone(),
+        width,
+        height,
+        blank,
+    };
+    let Some((x0, y0, x1, y1)) = bounds else {
+        return Ok(unchanged(true));
+    };
+    let (trim_w, trim_h) = (x1 - x0 + 1, y1 - y0 + 1);
+    if (trim_w, trim_h) == (width, height) {
+        return Ok(unchanged(false));
+    }
+    let trimmed = image.crop_imm(x0, y0, trim_w, trim_h);
+    Ok(TrimResult {
+        data_url: encode_keeping_alpha(&trimmed, image.color().has_alpha())?,
+        width: trim_w,
+        height: trim_h,
+        blank: false,
+    })
+}
+
+/// Scores a photo for readability. `sharpness` is the variance of the
+/// Laplacian over a downscaled luma copy; `brightness` is mean luma in 0..1.
+/// `is_blurry` trips when sharpness falls below `blur_threshold` (default 100).
+#[tauri::command(async)]
+pub(crate) fn assess_quality(
+    data_url: String,
+    blur_threshold: Option<f64>,
+) -> Result<ImageQuality, String> {
+    let image = decode_data_url(&data_url)?;
+    let gray = image
+        .thumbnail(QUALITY_SAMPLE_DIM, QUALITY_SAMPLE_DIM)
+        .to_luma8();
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return Err("Image is too small to assess".to_string());
+    }
+
+    let brightness = gray.as_raw().iter().map(|p| *p as f64).sum::<f64>()
+        / (width as f64 * height as f64)
+        / 255.0;
+
+    let px = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let lap = px(x - 1, y) + px(x + 1, y) + px(x, y - 1) + px(x, y + 1) - 4.0 * px(x, y);
+            sum += lap;
+            sum_sq += lap * lap;
+        }
+    }
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    let sharpness = sum_sq / count - mean * mean;
+
+    Ok(ImageQuality {
+        sharpness,
+        brightness,
+        is_blurry: sharpness < blur_threshold.unwrap_or(DEFAULT_BLUR_THRESHOLD),
+    })
+}
+
+// Encodes to "jpeg" (with quality), "png" or lossless "webp". Output is
+// byte-for-byte reproducible for the same pixels and settings: none of these
+// encoders write timestamps, which content-addressed dedup relies on.
+fn encode_image(image: &DynamicImage, format: &str, quality: u8) -> Result<Vec<u8>, String> {
+    let mut out = Cursor::new(Vec::new());
+    match format {
+        "jpeg" | "jpg" => {
+            let encoder = JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100));
+            image
+                .to_rgb8()
+                .write_with_encoder(encoder)
+                .map_err(|e| e.to_string())?;
+        }
+        "png" => image
+            .write_to(&mut out, ImageFormat::Png)
+            .map_err(|e| e.to_string())?,
+        "webp" => image
+            .to_rgba8()
+            .write_to(&mut out, ImageFormat::WebP)
+            .map_err(|e| e.to_string())?,
+        other => return Err(format!("Unsupported output format: {}", other)),
+    }
+    Ok(out.into_inner())
+}
+
+fn encoded_size_at(
+    image: &DynamicImage,
+    max_dim: u32,
+    format: &str,
+    quality: u8,
+) -> Result<usize, String> {
+    let (width, height) = resized_dimensions(image.width(), image.height(), max_dim, "fit")?;
+    let resized = if (width, height) == (image.width(), image.height()) {
+        image.clone()
+    } else {
+        image.resize_exact(width, height, FilterType::Lanczos3)
+    };
+    Ok(encode_image(&resized, format, quality)?.len())
+}
+
+/// Finds the largest max_dim whose encode fits in `target_bytes`, by binary
+/// search over a single decoded copy. `quality` applies to JPEG only; PNG
+/// and WebP are encoded losslessly.
+#[tauri::command(async)]
+pub(crate) fn solve_max_dim_for_budget(
+    data_url: String,
+    target_bytes: usize,
+    format: String,
+    quality: Option<u8>,
+) -> Result<MaxDimSolution, String> {
+    let image = decode_data_url(&data_url)?;
+    let format = format.to_ascii_lowercase();
+    let quality = quality.unwrap_or(80);
+    let full = image.width().max(image.height());
+    if full == 0 {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+
+    let full_bytes = encoded_size_at(&image, full, &format, quality)?;
+    if full_bytes <= target_bytes {
+        return Ok(MaxDimSolution {
+            max_dim: full,
+            resulting_bytes: full_bytes,
+        });
+    }
+
+    let mut low = MIN_SOLVED_MAX_DIM.min(full);
+    let low_bytes = encoded_size_at(&image, low, &format, quality)?;
+    if low_bytes > target_bytes {
+        return Err(format!(
+            "Cannot fit under {} bytes; smallest attempt was {} bytes at max_dim {}",
+            target_bytes, low_bytes, low
+        ));
+    }
+    let mut best = MaxDimSolution {
+        max_dim: low,
+        resulting_bytes: low_bytes,
+    };
+    let mut high = full;
+    for _ in 0..MAX_DIM_SEARCH_STEPS {
+        if high - low <= 1 {
+            break;
+        }
+        let mid = low + (high - low) / 2;
+        let bytes = encoded_size_at(&image, mid, &format, quality)?;
+        if bytes <= target_bytes {
+            low = mid;
+            best = MaxDimSolution {
+                max_dim: mid,
+                resulting_bytes: bytes,
+            };
+        } else {
+            high = mid;
+        }
+    }
+    Ok(best)
+}
+
+/// Reads a local image file into a data URL. The mime type is sniffed from
+/// the content alone, so a file whose bytes don't start with a known image
+/// signature is refused whatever its extension.
+#[tauri::command(async)]
+pub(crate) fn file_to_data_url(path: String, max_bytes: Option<u64>) -> Result<String, String> {
+    let max_bytes = max_bytes.unwrap_or(DEFAULT_MAX_FILE_BYTES);
+    let size = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+    if size > max_bytes {
+        return Err(format!(
+            "File is {} bytes, larger than the {} byte limit",
+            size, max_bytes
+        ));
+    }
+    let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+    if bytes.is_empty() {
+        return Err(EMPTY_IMAGE_DATA.to_string());
+    }
+    let mime_type = image::guess_format(&bytes)
+        .map(|format| format.to_mime_type())
+        .map_err(|_| format!("Bad input: {} is not a recognised image", path))?;
+    Ok(build_data_url(mime_type, &bytes))
+}
+
+/// Heuristic 0..1 score for "this looks like a paper document". It rewards a
+/// mostly light background, low colour saturation and text-like edge density
+/// in the central region. Not a classifier; meant for a gentle UI prompt.
+#[tauri::command(async)]
+pub(crate) fn looks_like_document(data_url: String) -> Result<DocumentScore, String> {
+    let image = decode_data_url(&data_url)?.thumbnail(DOCUMENT_SAMPLE_DIM, DOCUMENT_SAMPLE_DIM);
+    let rgb = image.to_rgb8();
+    let gray = image.to_luma8();
+    let (width, height) = gray.dimensions();
+    if width < 8 || height < 8 {
+        return Err("Image is too small to score".to_string());
+    }
+    let pixels = (width * height) as f64;
+
+    let light = gray.as_raw().iter().filter(|p| **p >= 170).count() as f64 / pixels;
+    let saturation = rgb
+        .pixels()
+        .map(|p| {
+            let max = p.0.iter().copied().max().unwrap_or(0) as f64;
+            let min = p.0.iter().copied().min().unwrap_or(0) as f64;
+            if max == 0.0 {
+                0.0
+            } else {
+                (max - min) / max
+            }
+        })
+        .sum::<f64>()
+        / pixels;
+
+    let (x0, x1) = (width / 6, width - width / 6);
+    let (y0, y1) = (height / 6, height - height / 6);
+    let mut edges = 0u32;
+    let mut samples = 0u32;
+    for y in y0.max(1)..y1.min(height - 1) {
+        for x in x0.max(1)..x1.min(width - 1) {
+            let gx = gray.get_pixel(x + 1, y)[0] as i32 - gray.get_pixel(x - 1, y)[0] as i32;
+            let gy = gray.get_pixel(x, y + 1)[0] as i32 - gray.get_pixel(x, y - 1)[0] as i32;
+            if gx.abs() + gy.abs() > 60 {
+                edges += 1;
+            }
+            samples += 1;
+        }
+    }
+    let edge_density = if samples == 0 {
+        0.0
+    } else {
+        edges as f64 / samples as f64
+    };
+    // Printed text typically covers roughly 5-25% of a page with sharp edges;
+    // blank pages and busy photos both fall outside that band.
+    let edge_score = if edge_density < 0.05 {
+        edge_density / 0.05
+    } else if edge_density <= 0.25 {
+        1.0
+    } else {
+        (1.0 - (edge_density - 0.25) / 0.5).max(0.0)
+    };
+
+    let score = 0.4 * light + 0.3 * (1.0 - saturation).clamp(0.0, 1.0) + 0.3 * edge_score;
+    Ok(DocumentScore {
+        score: score.clamp(0.0, 1.0),
+    })
+}
+
+fn resized_dimensions(
+    width: u32,
+    height: u32,
+    max_dim: u32,
+    mode: &str,
+) -> Result<(u32, u32), String> {
+    if width == 0 || height == 0 {
+        return Err("Source dimensions must be non-zero".to_string());
+    }
+    if max_dim == 0 {
+        return Ok((width, height));
+    }
+    let (w, h) = (width as f64, height as f64);
+    let target = max_dim as f64;
+    let scale = match mode {
+        // Longest side fits inside max_dim; never upscales.
+        "fit" => (target / w.max(h)).min(1.0),
+        // Shortest side covers max_dim, ready for a centre crop to a square.
+        "fill" => target / w.min(h),
+        "exact" => return Ok((max_dim, max_dim)),
+        other => return Err(format!("Unknown resize mode: {}", other)),
+    };
+    let out_w = ((w * scale).round() as u32).max(1);
+    let out_h = ((h * scale).round() as u32).max(1);
+    Ok((out_w, out_h))
+}
+
+/// Computes output dimensions for a resize without decoding anything, so the
+/// UI can preview "will be W x H" live. `mode` is "fit" (default), "fill"
+/// or "exact" for a `max_dim` square box as in resize_image; "fill" reports
+/// the covering size before the centre crop. A max_dim of 0 keeps the
+/// original size.
+#[tauri::command]
+pub(crate) fn compute_resized_dimensions(
+    width: u32,
+    height: u32,
+    max_dim: u32,
+    mode: Option<String>,
+) -> Result<Dimensions, String> {
+    let (width, height) =
+        resized_dimensions(width, height, max_dim, mode.as_deref().unwrap_or("fit"))?;
+    Ok(Dimensions { width, height })
+}
+
+/// Reports what parse_data_url makes of a data URL so malformed input (bad
+/// base64, missing header) is diagnosed before any heavier command runs.
+/// `isImage` only checks that the header parses, not the full pixel data.
+#[tauri::command(async)]
+pub(crate) fn validate_data_url(data_url: String) -> Result<DataUrlInfo, String> {
+    let (mime_type, bytes) = parse_data_url(&data_url)?;
+    let detected = image::guess_format(&bytes).ok();
+    let is_image = detected.is_some()
+        && ImageReader::new(Cursor::new(&bytes))
+            .with_guessed_format()
+            .map_err(|e| e.to_string())?
+            .into_dimensions()
+            .is_ok();
+    Ok(DataUrlInfo {
+        mime_type,
+        bytes: bytes.len(),
+        is_image,
+        detected_format: detected.map(|f| f.to_mime_type().to_string()),
+    })
+}
+
+fn dither_indices(
+    rgba: &image::RgbaImage,
+    quant: &color_quant::NeuQuant,
+    palette: &[u8],
+) -> Vec<u8> {
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    let mut work: Vec<[f32; 4]> = rgba.pixels().map(|p| p.0.map(|c| c as f32)).collect();
+    let mut indices = vec![0u8; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let pixel = work[i].map(|c| c.round().clamp(0.0, 255.0) as u8);
+            let index = quant.index_of(&pixel);
+            indices[i] = index as u8;
+            let chosen = &palette[index * 4..index * 4 + 4];
+            let error: [f32; 4] = std::array::from_fn(|c| work[i][c] - chosen[c] as f32);
+            // Floyd-Steinberg weights: right 7, below-left 3, below 5, below-right 1.
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                let ny = y + dy;
+                if nx >= 0 && (nx as usize) < width && ny < height {
+                    let target = &mut work[ny * width + nx as usize];
+                    for c in 0..4 {
+                        target[c] += error[c] * weight;
+                    }
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    indices
+}
+
+/// Reduces an image to at most `max_colors` (2-256) and encodes it as an
+/// indexed PNG, which is far smaller than JPEG/WebP for logo-like inputs.
+/// Optional Floyd-Steinberg dithering smooths gradients at the cost of size.
+#[tauri::command(async)]
+pub(crate) fn quantize_image(
+    data_url: String,
+    max_colors: usize,
+    dither: bool,
+) -> Result<String, String> {
+    let rgba = decode_data_url(&data_url)?.to_rgba8();
+    let colors = max_colors.clamp(2, MAX_PALETTE_COLORS);
+    let quant = color_quant::NeuQuant::new(QUANTIZE_SAMPLE_FACTOR, colors, rgba.as_raw());
+    let palette = quant.color_map_rgba();
+    let indices = if dither {
+        dither_indices(&rgba, &quant, &palette)
+    } else {
+        rgba.pixels().map(|p| quant.index_of(&p.0) as u8).collect()
+    };
+
+    let (rgb, alpha): (Vec<[u8; 3]>, Vec<u8>) = palette
+        .chunks_exact(4)
+        .map(|c| ([c[0], c[1], c[2]], c[3]))
+        .unzip();
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, rgba.width(), rgba.height());
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(rgb.concat());
+        if alpha.iter().any(|&a| a < u8::MAX) {
+            encoder.set_trns(alpha);
+        }
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&indices)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(build_data_url("image/png", &out))
+}
+
+fn nearest_centroid(pixel: [f64; 3], centroids: &[[f64; 3]]) -> usize {
+    let distance = |c: &[f64; 3]| (0..3).map(|i| (pixel[i] - c[i]).powi(2)).sum::<f64>();
+    (0..centroids.len())
+        .min_by(|&a, &b| distance(&centroids[a]).total_cmp(&distance(&centroids[b])))
+        .unwrap_or(0)
+}
+
+/// Returns the `count` most dominant colours with their coverage, largest
+/// first, via a small k-means over a 64px thumbnail. Fully transparent pixels
+/// are ignored so logos on clear backgrounds aren't dominated by black.
+#[tauri::command(async)]
+pub(crate) fn dominant_colors(
+    data_url: String,
+    count: usize,
+) -> Result<Vec<DominantColor>, String> {
+    let image = decode_data_url(&data_url)?;
+    let thumb = image
+        .resize(COLOR_SAMPLE_DIM, COLOR_SAMPLE_DIM, FilterType::Triangle)
+        .to_rgba8();
+    let pixels: Vec<[f64; 3]> = thumb
+        .pixels()
+        .filter(|p| p.0[3] > 0)
+        .map(|p| [p.0[0] as f64, p.0[1] as f64, p.0[2] as f64])
+        .collect();
+    let k = count.min(pixels.len());
+    if k == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Seed from evenly spaced pixels so results are deterministic.
+    let mut centroids: Vec<[f64; 3]> = (0..k).map(|i| pixels[i * pixels.len() / k]).collect();
+    let mut assignments = vec![0usize; pixels.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        for (slot, pixel) in assignments.iter_mut().zip(&pixels) {
+            *slot = nearest_centroid(*pixel, &centroids);
+        }
+        let mut sums = vec![[0.0f64; 3]; k];
+        let mut counts = vec![0usize; k];
+        for (&cluster, pixel) in assignments.iter().zip(&pixels) {
+            counts[cluster] += 1;
+            for c in 0..3 {
+                sums[cluster][c] += pixel[c];
+            }
+        }
+        for cluster in 0..k {
+            if counts[cluster] > 0 {
+                centroids[cluster] = sums[cluster].map(|v| v / counts[cluster] as f64);
+            }
+        }
+    }
+
+    let mut counts = vec![0usize; k];
+    for &cluster in &assignments {
+        counts[cluster] += 1;
+    }
+    let mut colors: Vec<DominantColor> = centroids
+        .iter()
+        .zip(&counts)
+        .filter(|(_, &n)| n > 0)
+        .map(|(c, &n)| DominantColor {
+            rgb: c.map(|v| v.round().clamp(0.0, 255.0) as u8),
+            fraction: n as f64 / pixels.len() as f64,
+        })
+        .collect();
+    colors.sort_by(|a, b| b.fraction.total_cmp(&a.fraction));
+    Ok(colors)
+}
+
+/// Reports whether an image actually uses its alpha channel. Many PNGs carry
+/// an alpha channel that is fully opaque; those are safe to encode as JPEG.
+#[tauri::command(async)]
+pub(crate) fn has_transparency(data_url: String) -> Result<bool, String> {
+    let image = decode_data_url(&data_url)?;
+    if !image.color().has_alpha() {
+        return Ok(false);
+    }
+    Ok(image.to_rgba8().pixels().any(|p| p.0[3] < u8::MAX))
+}
+
+/// Times base64 encode/decode of a `size_kb` buffer with the same engine the
+/// data URL helpers use, in megabytes per second, to show how much of an IPC
+/// round trip is spent on base64 on this device.
+#[tauri::command(async)]
+pub(crate) fn benchmark_base64(size_kb: u32) -> Result<Base64Benchmark, String> {
+    if size_kb == 0 || size_kb > MAX_BENCHMARK_KB {
+        return Err(format!(
+            "size_kb must be between 1 and {}",
+            MAX_BENCHMARK_KB
+        ));
+    }
+    let bytes: Vec<u8> = (0..size_kb as usize * 1024)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let megabytes = bytes.len() as f64 / (1024.0 * 1024.0);
+
+    let started = std::time::Instant::now();
+    let encoded = general_purpose::STANDARD.encode(&bytes);
+    let encode_secs = started.elapsed().as_secs_f64();
+
+    let started = std::time::Instant::now();
+    let decoded = general_purpose::STANDARD
+        .decode(&encoded)
+        .map_err(|e| e.to_string())?;
+    let decode_secs = started.elapsed().as_secs_f64();
+    std::hint::black_box(decoded);
+
+    let rate = |secs: f64| megabytes / secs.max(f64::EPSILON);
+    Ok(Base64Benchmark {
+        decode_mbps: rate(decode_secs),
+        encode_mbps: rate(encode_secs),
+    })
+}
+
+/// Maps a MIME type (or bare subtype such as "jpeg") to the file extension
+/// used when saving it. Shared with the frontend so ".jpeg" vs ".jpg" can't
+/// drift; unknown types fall back to "bin".
+#[tauri::command]
+pub(crate) fn extension_for_mime(mime: String) -> String {
+    let essence = mime
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let subtype = essence.rsplit('/').next().unwrap_or_default();
+    match subtype {
+        "jpeg" | "jpg" | "pjpeg" => "jpg",
+        "png" => "png",
+        "webp" => "webp",
+        "gif" => "gif",
+        "bmp" => "bmp",
+        "tiff" => "tiff",
+        "avif" => "avif",
+        "heic" => "heic",
+        "pdf" => "pdf",
+        "plain" => "txt",
+        "json" => "json",
+        _ => "bin",
+    }
+    .to_string()
+}
+
+// Edited images stay PNG when the source had transparency and otherwise go
+// back to JPEG, which is what receipts are in practice.
+fn encode_keeping_alpha(image: &DynamicImage, has_alpha: bool) -> Result<String, String> {
+    let (format, mime_type) = if has_alpha {
+        ("png", "image/png")
+    } else {
+        ("jpeg", "image/jpeg")
+    };
+    let bytes = encode_image(image, format, DERIVED_JPEG_QUALITY)?;
+    Ok(build_data_url(mime_type, &bytes))
+}
+
+/// Alpha-blends `overlay` (e.g. a signature PNG) onto `base` at (x, y) after
+/// scaling it by `scale`, with `opacity` in 0..=1 applied on top of any alpha
+/// the overlay has. Parts falling outside the base are clipped; the scaled
+/// overlay may be at most twice the base's width and height. Returns a PNG
+/// when the base has transparency, otherwise a JPEG.
+#[tauri::command(async)]
+pub(crate) fn overlay_image(
+    base_data_url: String,
+    overlay_data_url: String,
+    x: i64,
+    y: i64,
+    scale: f32,
+    opacity: f32,
+) -> Result<String, String> {
+    if !(scale > 0.0 && scale.is_finite()) {
+        return Err("Bad input: scale must be greater than 0".to_string());
+    }
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err("Bad input: opacity must be between 0 and 1".to_string());
+    }
+    let base = decode_data_url(&base_data_url)?;
+    let overlay = decode_data_url(&overlay_data_url)?;
+    let scaled_w = (overlay.width() as f64 * scale as f64).round();
+    let scaled_h = (overlay.height() as f64 * scale as f64).round();
+    if scaled_w > base.width() as f64 * MAX_OVERLAY_SCALE_RATIO
+        || scaled_h > base.height() as f64 * MAX_OVERLAY_SCALE_RATIO
+    {
+        return Err(format!(
+            "Bad input: scaled overlay ({}x{}) is too large for the {}x{} base image",
+            scaled_w,
+            scaled_h,
+            base.width(),
+            base.height()
+        ));
+    }
+    let width = (scaled_w as u32).max(1);
+    let height = (scaled_h as u32).max(1);
+    let in_bounds = x < base.width() as i64
+        && y < base.height() as i64
+        && x + width as i64 > 0
+        && y + height as i64 > 0;
+    if !in_bounds {
+        return Err("Bad input: overlay lies entirely outside the base image".to_string());
+    }
+
+    let mut overlay = overlay
+        .resize_exact(width, height, FilterType::Lanczos3)
+        .to_rgba8();
+    for pixel in overlay.pixels_mut() {
+        pixel.0[3] = (pixel.0[3] as f32 * opacity).round() as u8;
+    }
+    let has_alpha = base.color().has_alpha();
+    let mut canvas = base.to_rgba8();
+    image::imageops::overlay(&mut canvas, &overlay, x, y);
+    encode_keeping_alpha(&DynamicImage::ImageRgba8(canvas), has_alpha)
+}
+
+/// Splits an image into a `rows` x `cols` grid, returned in row-major order,
+/// for reading very long receipts in segments. Each tile extends `overlap`
+/// pixels into its neighbours (clipped at the edges) so no line is cut.
+#[tauri::command(async)]
+pub(crate) fn tile_image(
+    data_url: String,
+    rows: u32,
+    cols: u32,
+    overlap: u32,
+) -> Result<Vec<String>, String> {
+    if rows == 0 || cols == 0 {
+        return Err("Bad input: rows and cols must be at least 1".to_string());
+    }
+    let image = decode_data_url(&data_url)?;
+    let (width, height) = (image.width(), image.height());
+    if rows > height || cols > width {
+        return Err(format!(
+            "Bad input: a {}x{} image cannot be split into {} rows and {} cols",
+            width, height, rows, cols
+        ));
+    }
+    // Proportional bounds spread the remainder pixels across the grid, so
+    // every tile is non-empty whenever rows <= height and cols <= width.
+    let bound =
+        |index: u32, extent: u32, parts: u32| (index as u64 * extent as u64 / parts as u64) as u32;
+    let has_alpha = image.color().has_alpha();
+
+    let mut tiles = Vec::with_capacity((rows * cols) as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            let x0 = bound(col, width, cols).saturating_sub(overlap);
+            let y0 = bound(row, height, rows).saturating_sub(overlap);
+            let x1 = bound(col + 1, width, cols)
+                .saturating_add(overlap)
+                .min(width);
+            let y1 = bound(row + 1, height, rows)
+                .saturating_add(overlap)
+                .min(height);
+            let tile = image.crop_imm(x0, y0, x1 - x0, y1 - y0);
+            tiles.push(encode_keeping_alpha(&tile, has_alpha)?);
+        }
+    }
+    Ok(tiles)
+}
+
+const EXECUTABLE_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"MZ", "pe"),
+    (b"\x7fELF", "elf"),
+    (b"\xfe\xed\xfa\xce", "mach-o"),
+    (b"\xfe\xed\xfa\xcf", "mach-o"),
+    (b"\xce\xfa\xed\xfe", "mach-o"),
+    (b"\xcf\xfa\xed\xfe", "mach-o"),
+    (b"\xca\xfe\xba\xbe", "mach-o"),
+    (b"#!", "shebang"),
+];
+const SCRIPT_MARKERS: &[&[u8]] = &[b"<script", b"<?php"];
+
+/// Positively rejects payloads that look executable (PE/ELF/Mach-O headers,
+/// shebangs) or carry embedded script markup, then requires a recognised
+/// image format. `detected` names the executable kind, "script", the image
+/// mime type or "unknown".
+#[tauri::command(async)]
+pub(crate) fn is_safe_image_payload(data_url: String) -> Result<PayloadCheck, String> {
+    let (_, bytes) = parse_data_url(&data_url)?;
+    let check = |safe: bool, detected: &str| PayloadCheck {
+        safe,
+        detected: detected.to_string(),
+    };
+    if let Some((_, kind)) = EXECUTABLE_SIGNATURES
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+    {
+        return Ok(check(false, kind));
+    }
+    let has_script = SCRIPT_MARKERS.iter().any(|marker| {
+        bytes
+            .windows(marker.len())
+            .any(|window| window.eq_ignore_ascii_case(marker))
+    });
+    if has_script {
+        return Ok(check(false, "script"));
+    }
+    Ok(match image::guess_format(&bytes) {
+        Ok(format) => check(true, format.to_mime_type()),
+        Err(_) => check(false, "unknown"),
+    })
+}
+
+fn otsu_threshold(gray: &image::GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total = gray.pixels().len() as f64;
+    let weighted_total: f64 = (0..256).map(|i| i as f64 * histogram[i] as f64).sum();
+    let (mut background, mut background_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0u8, 0.0);
+    for (t, &count) in histogram.iter().enumerate() {
+        background += count as f64;
+        if background == 0.0 {
+            continue;
+        }
+        let foreground = total - background;
+        if foreground == 0.0 {
+            break;
+        }
+        background_sum += t as f64 * count as f64;
+        let mean_b = background_sum / background;
+        let mean_f = (weighted_total - background_sum) / foreground;
+        let variance = background * foreground * (mean_b - mean_f).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = t as u8;
+        }
+    }
+    best
+}
+
+// Local-mean thresholding over a (2r+1)^2 window using a summed-area table,
+// which copes with shadows and uneven lighting across a photographed page.
+fn adaptive_binarize(gray: &image::GrayImage, offset: u8) -> Vec<bool> {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let mut integral = vec![0u64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0u64;
+        for x in 0..width {
+            row_sum += gray.get_pixel(x as u32, y as u32).0[0] as u64;
+            integral[(y + 1) * (width + 1) + x + 1] = integral[y * (width + 1) + x + 1] + row_sum;
+        }
+    }
+    let radius = ADAPTIVE_WINDOW_RADIUS as usize;
+    let mut white = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            let sum = integral[y1 * (width + 1) + x1] + integral[y0 * (width + 1) + x0]
+                - integral[y0 * (width + 1) + x1]
+                - integral[y1 * (width + 1) + x0];
+            let mean = sum as f64 / ((x1 - x0) * (y1 - y0)) as f64;
+            let value = gray.get_pixel(x as u32, y as u32).0[0] as f64;
+            white.push(value >= mean - offset as f64);
+        }
+    }
+    white
+}
+
+/// Converts an image to pure black and white and encodes it as a 1-bit PNG
+/// for systems that only ingest bilevel documents. `method` is "otsu"
+/// (global; `threshold` overrides the computed level) or "adaptive" (local
+/// mean; `threshold` is how far below the mean a pixel must be to turn black).
+#[tauri::command(async)]
+pub(crate) fn binarize_image(
+    data_url: String,
+    method: String,
+    threshold: Option<u8>,
+) -> Result<String, String> {
+    let gray = decode_data_url(&data_url)?.to_luma8();
+    let white: Vec<bool> = match method.as_str() {
+        "otsu" => {
+            let level = threshold.unwrap_or_else(|| otsu_threshold(&gray));
+            gray.pixels().map(|p| p.0[0] > level).collect()
+        }
+        "adaptive" => adaptive_binarize(&gray, threshold.unwrap_or(DEFAULT_ADAPTIVE_OFFSET)),
+        other => return Err(format!("Unknown binarization method: {}", other)),
+    };
+
+    let width = gray.width() as usize;
+    let stride = width.div_ceil(8);
+    let mut packed = vec![0u8; stride * gray.height() as usize];
+    for (i, _) in white.iter().enumerate().filter(|(_, &w)| w) {
+        let (y, x) = (i / width, i % width);
+        packed[y * stride + x / 8] |= 0x80 >> (x % 8);
+    }
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, gray.width(), gray.height());
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&packed)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(build_data_url("image/png", &out))
+}
+
+/// Renders what changed between two versions of a receipt: `a` is shown as
+/// a faded grayscale backdrop with per-pixel differences painted in red,
+/// stronger where the change is larger. `b` is resized to `a` if needed.
+#[tauri::command(async)]
+pub(crate) fn diff_images(a_data_url: String, b_data_url: String) -> Result<String, String> {
+    let a = decode_data_url(&a_data_url)?.to_rgb8();
+    let b = decode_data_url(&b_data_url)?;
+    let b = if (b.width(), b.height()) == a.dimensions() {
+        b.to_rgb8()
+    } else {
+        b.resize_exact(a.width(), a.height(), FilterType::Triangle)
+            .to_rgb8()
+    };
+
+    let heatmap = image::RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y).0, b.get_pixel(x, y).0);
+        let diff = (0..3).map(|c| pa[c].abs_diff(pb[c])).max().unwrap_or(0) as f32 / 255.0;
+        let luma = (0.299 * pa[0] as f32 + 0.587 * pa[1] as f32 + 0.114 * pa[2] as f32) * 0.5;
+        let backdrop = luma + 64.0;
+        let mix = |target: f32| (backdrop + (target - backdrop) * diff).round() as u8;
+        image::Rgb([mix(255.0), mix(0.0), mix(0.0)])
+    });
+    encode_keeping_alpha(&DynamicImage::ImageRgb8(heatmap), false)
+}
+
+/// Fast JPEG preview for the editor's quality slider: a Triangle resize to
+/// fit `max_dim` (default 1024) and a single encode at `quality`, with no
+/// format or size search. The output is for on-screen preview only and
+/// should not be stored or uploaded; save through the full-quality path.
+#[tauri::command(async)]
+pub(crate) fn compress_preview(
+    data_url: String,
+    quality: u8,
+    max_dim: Option<u32>,
+) -> Result<String, String> {
+    let image = decode_data_url(&data_url)?;
+    let (width, height) = resized_dimensions(
+        image.width(),
+        image.height(),
+        max_dim.unwrap_or(PREVIEW_MAX_DIM),
+        "fit",
+    )?;
+    let image = if (width, height) == (image.width(), image.height()) {
+        image
+    } else {
+        image.resize_exact(width, height, FilterType::Triangle)
+    };
+    let bytes = encode_image(&image, "jpeg", quality)?;
+    Ok(build_data_url("image/jpeg", &bytes))
+}
+
+/// Reads just enough of the file header to report its dimensions and format,
+/// never decoding pixels, so an import grid can lay out many files quickly.
+#[tauri::command]
+pub(crate) fn image_dimensions(path: String) -> Result<FileDimensions, String> {
+    let reader = ImageReader::open(&path)
+        .map_err(|e| format!("{}: {}", path, e))?
+        .with_guessed_format()
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let ico_frame = ico_frame_dimensions(&path, reader.format());
+    let format = reader
+        .format()
+        .map(|format| format!("{:?}", format).to_lowercase());
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| format!("{}: {}", path, e))?;
+    Ok(FileDimensions {
+        width,
+        height,
+        format,
+        ico_frame,
+    })
+}
+
+// Accepts either a data URL or a local file path.
+fn decode_source(source: &str) -> Result<DynamicImage, String> {
+    if source.trim_start().starts_with("data:") {
+        decode_data_url(source)
+    } else {
+        let bytes = std::fs::read(source).map_err(|e| format!("{}: {}", source, e))?;
+        decode_image(&bytes)
+    }
+}
+
+/// Quick import preview no larger than `target` on its longest side, from a
+/// data URL or file path. Decoders that can downscale while decoding would
+/// be used here, but the image crate's JPEG decoder has no DCT scaling, so
+/// every format falls back to a full decode followed by a fast thumbnail
+/// resize. Returns a PNG when the source has transparency, otherwise a JPEG.
+#[tauri::command(async)]
+pub(crate) fn decode_low_res_preview(source: String, target: u32) -> Result<String, String> {
+    if target == 0 {
+        return Err("Bad input: target must be at least 1".to_string());
+    }
+    let image = decode_source(&source)?;
+    let has_alpha = image.color().has_alpha();
+    let preview = if image.width().max(image.height()) > target {
+        image.thumbnail(target, target)
+    } else {
+        image
+    };
+    encode_keeping_alpha(&preview, has_alpha)
+}
+
+/// Crops the region (x, y, w, h) out of an image given as a data URL or file
+/// path, then fits it within `max_dim` (0 keeps the cropped size). The image
+/// crate has no region decoding, so the full image is decoded first.
+#[tauri::command(async)]
+pub(crate) fn decode_region(
+    source: String,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    max_dim: u32,
+) -> Result<String, String> {
+    let image = decode_source(&source)?;
+    if w == 0 || h == 0 {
+        return Err("Bad input: region width and height must be non-zero".to_string());
+    }
+    let fits = x.checked_add(w).is_some_and(|right| right <= image.width())
+        && y.checked_add(h)
+            .is_some_and(|bottom| bottom <= image.height());
+    if !fits {
+        return Err(format!(
+            "Bad input: region {}x{} at ({}, {}) is outside the {}x{} image",
+            w,
+            h,
+            x,
+            y,
+            image.width(),
+            image.height()
+        ));
+    }
+    let region = image.crop_imm(x, y, w, h);
+    let (width, height) = resized_dimensions(w, h, max_dim, "fit")?;
+    let region = if (width, height) == (w, h) {
+        region
+    } else {
+        region.resize_exact(width, height, FilterType::Lanczos3)
+    };
+    encode_keeping_alpha(&region, image.color().has_alpha())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_image() -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 48, |x, y| {
+            image::Rgba([(x * 4) as u8, (y * 5) as u8, ((x + y) * 3) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn encode_image_is_deterministic() {
+        let image = sample_image();
+        for format in ["jpeg", "png", "webp"] {
+            let first = encode_image(&image, format, 80).unwrap();
+            let second = encode_image(&image, format, 80).unwrap();
+            assert_eq!(first, second, "{} output differs between runs", format);
+        }
+    }
+
+    #[test]
+    fn fit_shrinks_longest_side_without_upscaling() {
+        assert_eq!(
+            resized_dimensions(4000, 3000, 2000, "fit"),
+            Ok((2000, 1500))
+        );
+        assert_eq!(
+            resized_dimensions(3000, 4000, 2000, "fit"),
+            Ok((1500, 2000))
+        );
+        assert_eq!(resized_dimensions(800, 600, 2000, "fit"), Ok((800, 600)));
+    }
+
+    #[test]
+    fn fill_covers_max_dim_with_shortest_side() {
+        assert_eq!(
+            resized_dimensions(4000, 3000, 1000, "fill"),
+            Ok((1333, 1000))
+        );
+        assert_eq!(resized_dimensions(300, 600, 900, "fill"), Ok((900, 1800)));
+    }
+
+    #[test]
+    fn exact_and_zero_max_dim() {
+        assert_eq!(resized_dimensions(4000, 3000, 512, "exact"), Ok((512, 512)));
+        assert_eq!(resized_dimensions(4000, 3000, 0, "fit"), Ok((4000, 3000)));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(resized_dimensions(0, 100, 50, "fit").is_err());
+        assert!(resized_dimensions(100, 100, 50, "stretch").is_err());
+        assert_eq!(resized_dimensions(10_000, 1, 100, "fit"), Ok((100, 1)));
+    }
+
+    #[test]
+    fn tiles_cover_the_image_without_empty_columns() {
+        let png = encode_image(&sample_image().crop_imm(0, 0, 5, 1), "png", 100).unwrap();
+        let tiles = tile_image(build_data_url("image/png", &png), 1, 4, 0).unwrap();
+        let widths: Vec<u32> = tiles
+            .iter()
+            .map(|tile| decode_data_url(tile).unwrap().width())
+            .collect();
+        assert_eq!(widths, vec![1, 1, 1, 2]);
+    }
+
+    #[test]
+    fn resize_modes_honour_the_target_box() {
+        let png = encode_image(&sample_image(), "png", 100).unwrap();
+        let data_url = build_data_url("image/png", &png);
+        let size = |mode: &str, width: u32, height: u32| {
+            let out = resize_image(data_url.clone(), width, height, Some(mode.to_string()));
+            let image = decode_data_url(&out.unwrap()).unwrap();
+            (image.width(), image.height())
+        };
+        assert_eq!(size("fit", 32, 32), (32, 24));
+        assert_eq!(size("fit", 200, 200), (64, 48));
+        assert_eq!(size("fill", 30, 30), (30, 30));
+        assert_eq!(size("exact", 10, 40), (10, 40));
+    }
+
+    // Two PNG-compressed entries: a 16x16 at 32bpp listed first and a 64x64
+    // at 8bpp, which the ico decoder alone would pass over.
+    fn two_frame_ico() -> Vec<u8> {
+        let frames: Vec<(u8, u16, Vec<u8>)> = [(16u32, 32u16), (64, 8)]
+            .iter()
+            .map(|&(side, bpp)| {
+                let image = DynamicImage::ImageRgba8(image::RgbaImage::new(side, side));
+                (side as u8, bpp, encode_image(&image, "png", 100).unwrap())
+            })
+            .collect();
+        let mut ico = vec![0, 0, 1, 0, frames.len() as u8, 0];
+        let mut offset = (ICO_HEADER_LEN + frames.len() * ICO_ENTRY_LEN) as u32;
+        for (side, bpp, png) in &frames {
+            ico.extend_from_slice(&[*side, *side, 0, 0, 1, 0]);
+            ico.extend_from_slice(&bpp.to_le_bytes());
+            ico.extend_from_slice(&(png.len() as u32).to_le_bytes());
+            ico.extend_from_slice(&offset.to_le_bytes());
+            offset += png.len() as u32;
+        }
+        for (_, _, png) in &frames {
+            ico.extend_from_slice(png);
+        }
+        ico
+    }
+
+    #[test]
+    fn ico_decodes_the_largest_frame() {
+        let ico = two_frame_ico();
+        let (_, dimensions) = largest_ico_frame(&ico).unwrap();
+        assert_eq!(dimensions, (64, 64));
+        let image = decode_image(&ico).unwrap();
+        assert_eq!((image.width(), image.height()), (64, 64));
+    }
+
+    #[test]
+    fn quarter_turns_follow_the_exif_orientation_table() {
+        assert_eq!(rotate_orientation(1, 1), 6);
+        assert_eq!(rotate_orientation(6, 1), 3);
+        assert_eq!(rotate_orientation(2, 1), 7);
+        assert_eq!(rotate_orientation(8, 1), 1);
+    }
+
+    fn tiff_with_orientation(little_endian: bool, orientation: u16) -> Vec<u8> {
+        let mut writer = exif::experimental::Writer::new();
+        let field = exif::Field {
+            tag: exif::Tag::Orientation,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Short(vec![orientation]),
+        };
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, little_endian).unwrap();
+        tiff.into_inner()
+    }
+
+    #[test]
+    fn patched_orientation_round_trips_in_both_byte_orders() {
+        for little_endian in [false, true] {
+            let mut tiff = tiff_with_orientation(little_endian, 1);
+            assert!(patch_tiff_orientation(&mut tiff, 6));
+            let exif = exif::Reader::new().read_raw(tiff).unwrap();
+            assert_eq!(exif.little_endian(), little_endian);
+            let orientation = exif
+                .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0));
+            assert_eq!(orientation, Some(6));
+        }
+    }
+}
@@ -1,12 +1,40 @@
-use serde::Serialize;
+mod imaging;
+mod pdf;
+mod tls;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tauri::{Emitter, Manager};
-use rusqlite::{Connection, params};
-use std::collections::HashMap;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::{HashMap, VecDeque};
 use std::io::{self, Read};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const THROUGHPUT_HISTORY_LEN: usize = 20;
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 4 * 1024 * 1024;
+const DEFAULT_PROGRESS_THRESHOLD_BYTES: u64 = 32 * 1024;
+const LITE_PROGRESS_STEP_PCT: u8 = 5;
+const DEFAULT_MAX_REDIRECTS: u32 = 5;
+// Apps Script answers a POST with a redirect to googleusercontent.com.
+const DEFAULT_REDIRECT_HOSTS: &[&str] = &["google.com", "googleusercontent.com"];
+const UPLOAD_QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const UPLOAD_QUEUE_BASE_BACKOFF_MS: i64 = 5_000;
+const UPLOAD_QUEUE_MAX_BACKOFF_MS: i64 = 10 * 60 * 1000;
+const UPLOAD_QUEUE_MAX_ATTEMPTS: i64 = 8;
+const UPLOAD_CANCELLED: &str = "cancelled";
+const REJECTED_ERROR_PREFIX: &str = "Rejected: ";
+const MAX_VERIFY_DOWNLOAD_BYTES: u64 = 50 * 1024 * 1024;
+const OFFLINE_ERROR_PREFIX: &str = "Offline: ";
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Roughly one frame at 30fps; progress events closer together than this are
+// coalesced so a busy webview's event queue can't back up.
+const MIN_EMIT_INTERVAL: Duration = Duration::from_millis(33);
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -15,11 +43,81 @@ struct UploadProgress {
     loaded: u64,
     total: u64,
     done: bool,
+    /// "uploading" while the body streams, "finalizing" once it has been
+    /// handed off and the server response is awaited.
+    stage: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+struct UploadOptions {
+    max_response_bytes: Option<u64>,
+    lightweight_progress: bool,
+    cleanup_url: Option<String>,
+    /// Bodies smaller than this only report completion, not intermediate progress.
+    progress_threshold_bytes: Option<u64>,
+    on_complete_url: Option<String>,
+    /// HTTP verb for the upload: "POST" (default) or "PUT" for presigned URLs.
+    method: Option<String>,
+    max_redirects: Option<u32>,
+    /// Hosts (and their subdomains) a redirect may lead to. Defaults to the
+    /// Google domains Apps Script uses plus the upload URL's own host.
+    redirect_allowlist: Option<Vec<String>>,
+    /// Opaque correlation data echoed back in progress events and the result.
+    meta: Option<serde_json::Value>,
+    /// When the network is unreachable, save the upload to the spool
+    /// directory and return `{status: "spooled", spoolId}` instead of failing.
+    offline_spool: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpoolEntry {
+    upload_id: String,
+    url: String,
+    payload: serde_json::Value,
+    options: UploadOptions,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpooledUpload {
+    spool_id: String,
+    upload_id: String,
+    url: String,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpoolFlushResult {
+    flushed: usize,
+    failed: usize,
+}
+
+#[derive(Serialize, Clone)]
+struct UploadProgressLite {
+    id: String,
+    pct: u8,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ThroughputSample {
+    ts: i64,
+    bytes_per_sec: f64,
 }
 
 #[derive(Default)]
 struct UploadState {
     cancel_flags: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    throughput: Mutex<VecDeque<ThroughputSample>>,
+    uploads_disabled: AtomicBool,
+    agent: Mutex<Option<ureq::Agent>>,
+    flushing_spool: AtomicBool,
 }
 
 struct DbState {
@@ -32,6 +130,12 @@ struct CacheEntry {
     updated_at: i64,
 }
 
+#[derive(Serialize)]
+struct DataUsage {
+    date: String,
+    bytes: i64,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct SyncJob {
@@ -43,6 +147,42 @@ struct SyncJob {
     created_at: i64,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueuedUpload {
+    upload_id: String,
+    url: String,
+    attempts: i64,
+    last_error: Option<String>,
+    next_attempt_at: i64,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadVerification {
+    verified: bool,
+    actual_sha256: String,
+    possibly_transcoded: bool,
+    bytes: u64,
+    content_type: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TempFile {
+    path: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct UploadQueueEvent {
+    upload_id: String,
+    ok: bool,
+    error: Option<String>,
+    /// False when a failed upload stays queued for another attempt.
+    removed: bool,
+}
+
 impl UploadState {
     fn register(&self, upload_id: &str) -> Arc<AtomicBool> {
         let mut guard = self.cancel_flags.lock().unwrap();
@@ -60,10 +200,63 @@ impl UploadState {
         false
     }
 
+    fn cancel_matching(&self, prefix: &str) -> usize {
+        let guard = self.cancel_flags.lock().unwrap();
+        let mut cancelled = 0;
+        for (upload_id, flag) in guard.iter() {
+            if upload_id.starts_with(prefix) {
+                flag.store(true, Ordering::SeqCst);
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+
     fn remove(&self, upload_id: &str) {
         let mut guard = self.cancel_flags.lock().unwrap();
         guard.remove(upload_id);
     }
+
+    fn uploads_enabled(&self) -> bool {
+        !self.uploads_disabled.load(Ordering::SeqCst)
+    }
+
+    fn set_uploads_enabled(&self, enabled: bool) {
+        self.uploads_disabled.store(!enabled, Ordering::SeqCst);
+    }
+
+    // Agents never follow redirects themselves; uploads go through
+    // follow_redirects so the target host can be checked first.
+    fn agent(&self) -> ureq::Agent {
+        let guard = self.agent.lock().unwrap();
+        guard
+            .clone()
+            .unwrap_or_else(|| ureq::AgentBuilder::new().redirects(0).build())
+    }
+
+    fn set_agent(&self, agent: Option<ureq::Agent>) {
+        let mut guard = self.agent.lock().unwrap();
+        *guard = agent;
+    }
+
+    fn record_throughput(&self, bytes: u64, elapsed_secs: f64) {
+        if bytes == 0 || elapsed_secs <= 0.0 {
+            return;
+        }
+        let mut guard = self.throughput.lock().unwrap();
+        if guard.len() >= THROUGHPUT_HISTORY_LEN {
+            guard.pop_front();
+        }
+        guard.push_back(ThroughputSample {
+            ts: now_ms(),
+            bytes_per_sec: bytes as f64 / elapsed_secs,
+        });
+    }
+
+    fn throughput_history(&self) -> Vec<ThroughputSample> {
+        let guard = self.throughput.lock().unwrap();
+        guard.iter().cloned().collect()
+    }
 }
 
 fn now_ms() -> i64 {
@@ -73,6 +266,43 @@ fn now_ms() -> i64 {
         .unwrap_or(0)
 }
 
+// Formats a unix timestamp in milliseconds as a UTC `YYYY-MM-DD` date.
+fn utc_date(ms: i64) -> String {
+    let days = ms.div_euclid(86_400_000);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Free space on the volume holding `path`. The path itself need not exist yet;
+// the nearest existing ancestor is queried instead.
+fn available_space(path: &Path) -> Result<u64, String> {
+    let mut probe = path;
+    while !probe.exists() {
+        probe = probe
+            .parent()
+            .ok_or_else(|| format!("No existing directory for {}", path.display()))?;
+    }
+    fs4::available_space(probe).map_err(|e| e.to_string())
+}
+
+fn get_temp_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join("tmp");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
 fn get_db_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
     let dir = app
         .path()
@@ -96,6 +326,20 @@ fn init_db(conn: &Connection) -> Result<(), rusqlite::Error> {
             params TEXT NOT NULL,
             payload TEXT NOT NULL,
             created_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS data_usage (
+            date TEXT PRIMARY KEY,
+            bytes INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS upload_queue (
+            upload_id TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            options TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            next_attempt_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
         );",
     )?;
     Ok(())
@@ -121,9 +365,17 @@ struct ProgressReader<R: Read> {
     sent: u64,
     last_emit: u64,
     emit_every: u64,
+    last_emit_at: Option<Instant>,
     app: tauri::AppHandle,
     upload_id: String,
     cancel_flag: Arc<AtomicBool>,
+    lightweight: bool,
+    only_final: bool,
+    last_pct: Option<u8>,
+    hasher: Option<Sha256>,
+    digest: Arc<Mutex<Option<String>>>,
+    body_read_at: Option<Instant>,
+    meta: Option<serde_json::Value>,
 }
 
 impl<R: Read> ProgressReader<R> {
@@ -140,21 +392,81 @@ impl<R: Read> ProgressReader<R> {
             sent: 0,
             last_emit: 0,
             emit_every: 64 * 1024,
+            last_emit_at: None,
             app,
             upload_id,
             cancel_flag,
+            lightweight: false,
+            only_final: false,
+            last_pct: None,
+            hasher: Some(Sha256::new()),
+            digest: Arc::new(Mutex::new(None)),
+            body_read_at: None,
+            meta: None,
         }
     }
 
     fn emit(&mut self, done: bool) {
+        if self.lightweight {
+            self.emit_lite(done);
+            return;
+        }
         let payload = UploadProgress {
             upload_id: self.upload_id.clone(),
             loaded: self.sent,
             total: self.total,
             done,
+            stage: if done || self.sent >= self.total {
+                "finalizing"
+            } else {
+                "uploading"
+            },
+            meta: self.meta.clone(),
         };
         let _ = self.app.emit("upload-progress", payload);
         self.last_emit = self.sent;
+        self.last_emit_at = Some(Instant::now());
+    }
+
+    // Hashing as the body streams avoids a second pass over large payloads;
+    // the digest is published once the last byte has been read.
+    fn finish_digest(&mut self) {
+        if let Some(hasher) = self.hasher.take() {
+            if let Ok(mut digest) = self.digest.lock() {
+                *digest = Some(to_hex(&hasher.finalize()));
+            }
+        }
+    }
+
+    fn emitted_recently(&self) -> bool {
+        self.last_emit_at
+            .is_some_and(|at| at.elapsed() < MIN_EMIT_INTERVAL)
+    }
+
+    // Lightweight mode only reports whole-percent steps to keep the event
+    // stream cheap on low-power devices.
+    fn emit_lite(&mut self, done: bool) {
+        self.last_emit = self.sent;
+        let pct = if done {
+            100
+        } else {
+            self.sent
+                .saturating_mul(100)
+                .checked_div(self.total)
+                .map_or(99, |pct| pct.min(99) as u8)
+        };
+        if let Some(last) = self.last_pct {
+            if pct < last.saturating_add(LITE_PROGRESS_STEP_PCT) && !(done && last < 100) {
+                return;
+            }
+        }
+        let payload = UploadProgressLite {
+            id: self.upload_id.clone(),
+            pct,
+        };
+        let _ = self.app.emit("upload-progress-lite", payload);
+        self.last_pct = Some(pct);
+        self.last_emit_at = Some(Instant::now());
     }
 }
 
@@ -165,17 +477,239 @@ impl<R: Read> Read for ProgressReader<R> {
         }
         let read = self.inner.read(buf)?;
         if read == 0 {
-            self.emit(true);
+            self.finish_digest();
             return Ok(0);
         }
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..read]);
+        }
         self.sent = self.sent.saturating_add(read as u64);
-        if self.sent - self.last_emit >= self.emit_every || self.sent >= self.total {
-            self.emit(self.sent >= self.total);
+        // Reading the last byte only means ureq has buffered the body, so
+        // `done` is left to the caller once the response starts arriving.
+        let body_read = self.sent >= self.total;
+        if body_read {
+            self.finish_digest();
+            self.body_read_at.get_or_insert_with(Instant::now);
+        }
+        if self.only_final {
+            return Ok(read);
+        }
+        if body_read
+            || (self.sent - self.last_emit >= self.emit_every && !self.emitted_recently())
+        {
+            self.emit(false);
         }
         Ok(read)
     }
 }
 
+// DNS and connect failures mean the device can't reach the network at all,
+// as opposed to the server rejecting or timing out mid-request.
+fn describe_send_error(error: ureq::Error) -> String {
+    match &error {
+        ureq::Error::Transport(transport)
+            if matches!(
+                transport.kind(),
+                ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed
+            ) =>
+        {
+            format!("{}{}", OFFLINE_ERROR_PREFIX, error)
+        }
+        // Client errors won't succeed on a retry; 408 and 429 are the
+        // exceptions since they ask the client to try again later.
+        ureq::Error::Status(code, _) if (400..500).contains(code) && !matches!(code, 408 | 429) => {
+            format!("{}{}", REJECTED_ERROR_PREFIX, error)
+        }
+        _ => error.to_string(),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn redirect_host_allowed(host: &str, allowlist: &[String]) -> bool {
+    let host = host.to_ascii_lowercase();
+    allowlist.iter().any(|allowed| {
+        let allowed = allowed.trim().trim_start_matches('.').to_ascii_lowercase();
+        !allowed.is_empty() && (host == allowed || host.ends_with(&format!(".{}", allowed)))
+    })
+}
+
+fn default_redirect_allowlist(url: &str) -> Vec<String> {
+    let own_host = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string));
+    DEFAULT_REDIRECT_HOSTS
+        .iter()
+        .map(|h| h.to_string())
+        .chain(own_host)
+        .collect()
+}
+
+// Only 301/302/303 can be followed: they switch to a bodyless GET, while
+// 307/308 would need the already-streamed body to be sent again.
+fn follow_redirects(
+    agent: &ureq::Agent,
+    mut response: ureq::Response,
+    max_redirects: u32,
+    allowlist: &[String],
+) -> Result<ureq::Response, String> {
+    let mut followed = 0;
+    while (300..400).contains(&response.status()) {
+        let status = response.status();
+        if !matches!(status, 301..=303) {
+            return Err(format!("Unsupported redirect status {}", status));
+        }
+        if followed >= max_redirects {
+            return Err(format!("Too many redirects (limit {})", max_redirects));
+        }
+        let location = response
+            .header("Location")
+            .ok_or_else(|| format!("Redirect {} without a Location header", status))?;
+        let next = url::Url::parse(response.get_url())
+            .and_then(|base| base.join(location))
+            .map_err(|e| format!("Invalid redirect target: {}", e))?;
+        let host = next.host_str().unwrap_or_default();
+        if !redirect_host_allowed(host, allowlist) {
+            return Err(format!("Redirect to disallowed host: {}", host));
+        }
+        response = agent.get(next.as_str()).call().map_err(describe_send_error)?;
+        followed += 1;
+    }
+    Ok(response)
+}
+
+fn record_data_usage(app: &tauri::AppHandle, bytes: u64) -> Result<(), String> {
+    let state = app
+        .try_state::<DbState>()
+        .ok_or_else(|| "Database not initialised".to_string())?;
+    let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    conn.execute(
+        "INSERT INTO data_usage (date, bytes) VALUES (?1, ?2)
+         ON CONFLICT(date) DO UPDATE SET bytes = bytes + excluded.bytes",
+        params![utc_date(now_ms()), bytes as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn read_response_limited(response: ureq::Response, max_bytes: u64) -> Result<String, String> {
+    let mut buf = Vec::new();
+    response
+        .into_reader()
+        .take(max_bytes.saturating_add(1))
+        .read_to_end(&mut buf)
+        .map_err(|e| e.to_string())?;
+    if buf.len() as u64 > max_bytes {
+        return Err(format!("Response exceeded the {} byte limit", max_bytes));
+    }
+    String::from_utf8(buf).map_err(|e| e.to_string())
+}
+
+// Waits for a request running on a worker thread, giving up as soon as the
+// cancel flag is set. The worker may still be stuck in DNS or connect; it is
+// abandoned and its result dropped when it eventually finishes.
+fn wait_for_request<T>(
+    rx: mpsc::Receiver<Result<T, String>>,
+    cancel_flag: &AtomicBool,
+) -> Result<T, String> {
+    loop {
+        match rx.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Timeout) => {
+                if cancel_flag.load(Ordering::SeqCst) {
+                    return Err(UPLOAD_CANCELLED.to_string());
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err("Upload worker exited unexpectedly".to_string());
+            }
+        }
+    }
+}
+
+// Best-effort notice to the server that a cancelled upload's partial record
+// can be discarded. The upload id doubles as the idempotency key so the
+// server can match it to whatever chunks it already received.
+fn spawn_cleanup_request(agent: ureq::Agent, cleanup_url: String, upload_id: String) {
+    thread::spawn(move || {
+        let body = serde_json::json!({ "uploadId": upload_id });
+        if let Err(e) = agent
+            .post(&cleanup_url)
+            .set("Content-Type", "text/plain")
+            .set("Idempotency-Key", &upload_id)
+            .send_string(&body.to_string())
+        {
+            eprintln!("Upload cleanup for {} failed: {}", upload_id, e);
+        }
+    });
+}
+
+// Fire-and-forget notification to a local automation hook after a successful
+// upload. Failures are logged and never affect the upload result.
+fn spawn_completion_callback(agent: ureq::Agent, callback_url: String, upload_id: String, bytes: u64) {
+    thread::spawn(move || {
+        let body = serde_json::json!({
+            "uploadId": upload_id,
+            "status": "success",
+            "bytes": bytes,
+        });
+        if let Err(e) = agent
+            .post(&callback_url)
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+        {
+            eprintln!("Upload completion callback for {} failed: {}", upload_id, e);
+        }
+    });
+}
+
+#[tauri::command]
+fn check_free_space(path: String, required_bytes: u64) -> Result<bool, String> {
+    Ok(available_space(Path::new(&path))? >= required_bytes)
+}
+
+/// Writes a data URL's bytes to a uniquely named file in the app's temp
+/// directory, with the extension matching its mime type, for APIs that need
+/// a real file. Pair with cleanup_temp_file once the file is no longer needed.
+#[tauri::command]
+fn data_url_to_temp_file(app: tauri::AppHandle, data_url: String) -> Result<TempFile, String> {
+    let (mime_type, bytes) = imaging::parse_data_url(&data_url)?;
+    let dir = get_temp_dir(&app)?;
+    if available_space(&dir)? < bytes.len() as u64 {
+        return Err("Not enough free space for the temporary file".to_string());
+    }
+    let name = format!(
+        "{}-{}.{}",
+        now_ms(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+        imaging::extension_for_mime(mime_type)
+    );
+    let path = dir.join(name);
+    std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+    Ok(TempFile {
+        path: path.to_string_lossy().into_owned(),
+    })
+}
+
+/// Deletes a file created by data_url_to_temp_file. Paths outside the app's
+/// temp directory are refused so this can't be used to delete arbitrary files.
+#[tauri::command]
+fn cleanup_temp_file(app: tauri::AppHandle, path: String) -> Result<bool, String> {
+    let dir = get_temp_dir(&app)?.canonicalize().map_err(|e| e.to_string())?;
+    let target = match Path::new(&path).canonicalize() {
+        Ok(target) => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.to_string()),
+    };
+    if target.parent() != Some(dir.as_path()) || !target.is_file() {
+        return Err(format!("Refusing to delete {}: not an app temp file", path));
+    }
+    std::fs::remove_file(&target).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
 #[tauri::command]
 fn cache_get(state: tauri::State<DbState>, key: String) -> Result<Option<CacheEntry>, String> {
     let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
@@ -306,50 +840,547 @@ fn queue_count(state: tauri::State<DbState>) -> Result<i64, String> {
 }
 
 #[tauri::command]
+fn get_data_usage(state: tauri::State<DbState>, since_days: Option<u32>) -> Result<Vec<DataUsage>, String> {
+    let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    let since_days = since_days.unwrap_or(30) as i64;
+    let cutoff = utc_date(now_ms() - since_days * 86_400_000);
+    let mut stmt = conn
+        .prepare("SELECT date, bytes FROM data_usage WHERE date >= ?1 ORDER BY date ASC")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![cutoff], |row| {
+            Ok(DataUsage {
+                date: row.get(0)?,
+                bytes: row.get(1)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    let mut usage = Vec::new();
+    for row in rows {
+        usage.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(usage)
+}
+
+// Runs off the main thread so cancel_upload can be dispatched while this
+// blocks waiting for the request.
+#[tauri::command(async)]
 fn upload_payment_attachment(
     app: tauri::AppHandle,
     state: tauri::State<UploadState>,
     url: String,
     payload: serde_json::Value,
     upload_id: String,
+    options: Option<UploadOptions>,
 ) -> Result<serde_json::Value, String> {
+    perform_upload(&app, &state, url, payload, upload_id, options.unwrap_or_default())
+}
+
+fn perform_upload(
+    app: &tauri::AppHandle,
+    state: &UploadState,
+    url: String,
+    payload: serde_json::Value,
+    upload_id: String,
+    options: UploadOptions,
+) -> Result<serde_json::Value, String> {
+    if !state.uploads_enabled() {
+        return Err("Disabled: uploads are paused".to_string());
+    }
     if url.trim().is_empty() {
         return Err("Missing Apps Script URL".to_string());
     }
+    let spool_entry = options.offline_spool.then(|| SpoolEntry {
+        upload_id: upload_id.clone(),
+        url: url.clone(),
+        payload: payload.clone(),
+        options: UploadOptions {
+            offline_spool: false,
+            ..options.clone()
+        },
+        created_at: now_ms(),
+    });
     let body = serde_json::json!({
         "action": "uploadPaymentAttachment",
         "payload": payload,
     });
     let body_bytes = serde_json::to_vec(&body).map_err(|e| e.to_string())?;
     let total = body_bytes.len() as u64;
+    let method = match options.method.as_deref().map(str::to_ascii_uppercase) {
+        None => "POST".to_string(),
+        Some(m) if m == "POST" || m == "PUT" => m,
+        Some(m) => return Err(format!("Unsupported upload method: {}", m)),
+    };
+    let max_response_bytes = options.max_response_bytes.unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+    let lightweight_progress = options.lightweight_progress;
+    let max_redirects = options.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+    let redirect_allowlist = options
+        .redirect_allowlist
+        .clone()
+        .unwrap_or_else(|| default_redirect_allowlist(&url));
+    let only_final_progress =
+        total < options.progress_threshold_bytes.unwrap_or(DEFAULT_PROGRESS_THRESHOLD_BYTES);
     let cancel_flag = state.register(&upload_id);
+    let started = Instant::now();
 
-    let result = (|| {
-        let reader = ProgressReader::new(
-            io::Cursor::new(body_bytes),
-            total,
-            app.clone(),
-            upload_id.clone(),
-            cancel_flag.clone(),
-        );
-        let response = ureq::post(&url)
-            .set("Content-Type", "text/plain")
-            .set("Content-Length", &total.to_string())
-            .send(reader)
-            .map_err(|e| e.to_string())?;
-        let text = response.into_string().map_err(|e| e.to_string())?;
-        serde_json::from_str(&text).map_err(|e| e.to_string())
-    })();
+    let agent = state.agent();
+    let (tx, rx) = mpsc::channel();
+    let worker_flag = cancel_flag.clone();
+    let worker_id = upload_id.clone();
+    let worker_app = app.clone();
+    let meta = options.meta.clone();
+    thread::spawn(move || {
+        let result = (|| {
+            let mut reader = ProgressReader::new(
+                io::Cursor::new(body_bytes),
+                total,
+                worker_app,
+                worker_id,
+                worker_flag,
+            );
+            reader.lightweight = lightweight_progress;
+            reader.only_final = only_final_progress;
+            reader.meta = meta.clone();
+            // Always report 0% up front so the UI shows a determinate bar even
+            // if the connection stalls before the first threshold is crossed.
+            reader.emit(false);
+            let send_started = Instant::now();
+            let response = agent
+                .request(&method, &url)
+                .set("Content-Type", "text/plain")
+                .set("Content-Length", &total.to_string())
+                .send(&mut reader)
+                .map_err(describe_send_error)?;
+            let response_at = Instant::now();
+            reader.emit(true);
+            // upload_ms covers streaming the body; server_ms is the wait from
+            // the last body byte until the response headers arrived.
+            let body_read_at = reader.body_read_at.unwrap_or(response_at);
+            let upload_ms = body_read_at.duration_since(send_started).as_millis() as u64;
+            let server_ms = response_at.duration_since(body_read_at).as_millis() as u64;
+            let response = follow_redirects(&agent, response, max_redirects, &redirect_allowlist)?;
+            let text = read_response_limited(response, max_response_bytes)?;
+            let mut value: serde_json::Value =
+                serde_json::from_str(&text).map_err(|e| e.to_string())?;
+            let digest = reader.digest.lock().ok().and_then(|d| d.clone());
+            if let Some(obj) = value.as_object_mut() {
+                if let Some(digest) = digest {
+                    obj.insert("bodySha256".to_string(), serde_json::Value::String(digest));
+                }
+                obj.insert("uploadMs".to_string(), upload_ms.into());
+                obj.insert("serverMs".to_string(), server_ms.into());
+                if let Some(meta) = meta {
+                    obj.insert("meta".to_string(), meta);
+                }
+            }
+            Ok(value)
+        })();
+        let _ = tx.send(result);
+    });
+    let result = match wait_for_request(rx, &cancel_flag) {
+        // However the worker failed, a cancelled upload reports as cancelled.
+        Err(_) if cancel_flag.load(Ordering::SeqCst) => Err(UPLOAD_CANCELLED.to_string()),
+        result => result,
+    };
 
     state.remove(&upload_id);
+    if result.is_err() && cancel_flag.load(Ordering::SeqCst) {
+        if let Some(cleanup_url) = options.cleanup_url.filter(|u| !u.trim().is_empty()) {
+            spawn_cleanup_request(state.agent(), cleanup_url, upload_id.clone());
+        }
+    }
+    if result.is_ok() {
+        state.record_throughput(total, started.elapsed().as_secs_f64());
+        if let Err(e) = record_data_usage(app, total) {
+            eprintln!("Failed to record data usage: {}", e);
+        }
+        if let Some(callback_url) = options.on_complete_url.filter(|u| !u.trim().is_empty()) {
+            spawn_completion_callback(state.agent(), callback_url, upload_id.clone(), total);
+        }
+    }
+    match (result, spool_entry) {
+        (Err(e), Some(entry)) if e.starts_with(OFFLINE_ERROR_PREFIX) => {
+            let spool_id = spool_upload(app, &entry)?;
+            Ok(serde_json::json!({ "status": "spooled", "spoolId": spool_id }))
+        }
+        (result, _) => result,
+    }
+}
+
+fn get_spool_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("upload_spool");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+// Spool ids double as file names, so they are checked before touching disk.
+fn spool_path(app: &tauri::AppHandle, spool_id: &str) -> Result<PathBuf, String> {
+    let valid = !spool_id.is_empty()
+        && spool_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("Invalid spool id: {}", spool_id));
+    }
+    Ok(get_spool_dir(app)?.join(format!("{}.json", spool_id)))
+}
+
+fn spool_upload(app: &tauri::AppHandle, entry: &SpoolEntry) -> Result<String, String> {
+    let safe_id: String = entry
+        .upload_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    let spool_id = format!("{}-{}", entry.created_at, safe_id);
+    let json = serde_json::to_vec(entry).map_err(|e| e.to_string())?;
+    std::fs::write(spool_path(app, &spool_id)?, json).map_err(|e| e.to_string())?;
+    Ok(spool_id)
+}
+
+fn read_spool(app: &tauri::AppHandle) -> Result<Vec<(String, SpoolEntry)>, String> {
+    let mut entries = Vec::new();
+    for item in std::fs::read_dir(get_spool_dir(app)?).map_err(|e| e.to_string())? {
+        let path = item.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(spool_id) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+            continue;
+        };
+        let parsed = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|raw| serde_json::from_slice::<SpoolEntry>(&raw).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(entry) => entries.push((spool_id, entry)),
+            Err(e) => eprintln!("Skipping unreadable spool file {}: {}", path.display(), e),
+        }
+    }
+    entries.sort_by_key(|(_, entry)| entry.created_at);
+    Ok(entries)
+}
+
+#[tauri::command]
+fn list_spooled(app: tauri::AppHandle) -> Result<Vec<SpooledUpload>, String> {
+    Ok(read_spool(&app)?
+        .into_iter()
+        .map(|(spool_id, entry)| SpooledUpload {
+            spool_id,
+            upload_id: entry.upload_id,
+            url: entry.url,
+            created_at: entry.created_at,
+        })
+        .collect())
+}
+
+/// Retries every spooled upload in the order it was spooled. Successful
+/// uploads are removed from the spool; failures stay for the next flush.
+/// Only one flush runs at a time so an entry is never sent twice.
+#[tauri::command(async)]
+fn flush_spool(app: tauri::AppHandle, state: tauri::State<UploadState>) -> Result<SpoolFlushResult, String> {
+    if state.flushing_spool.swap(true, Ordering::SeqCst) {
+        return Err("Busy: a spool flush is already in progress".to_string());
+    }
+    let result = (|| {
+        let mut summary = SpoolFlushResult { flushed: 0, failed: 0 };
+        for (spool_id, entry) in read_spool(&app)? {
+            match perform_upload(&app, &state, entry.url, entry.payload, entry.upload_id, entry.options) {
+                Ok(_) => {
+                    let _ = std::fs::remove_file(spool_path(&app, &spool_id)?);
+                    summary.flushed += 1;
+                }
+                Err(e) => {
+                    eprintln!("Spooled upload {} failed: {}", spool_id, e);
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(summary)
+    })();
+    state.flushing_spool.store(false, Ordering::SeqCst);
     result
 }
 
+#[tauri::command]
+fn discard_spooled(app: tauri::AppHandle, spool_id: String) -> Result<bool, String> {
+    let path = spool_path(&app, &spool_id)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    std::fs::remove_file(path).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Persists an upload so it survives app restarts; the background worker
+/// sends it when uploads are enabled and retries connection failures with
+/// backoff.
+#[tauri::command]
+fn enqueue_upload(
+    state: tauri::State<DbState>,
+    url: String,
+    payload: serde_json::Value,
+    upload_id: String,
+    options: Option<UploadOptions>,
+) -> Result<(), String> {
+    if url.trim().is_empty() {
+        return Err("Missing Apps Script URL".to_string());
+    }
+    let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    let payload_json = serde_json::to_string(&payload).map_err(|e| e.to_string())?;
+    // The queue already retries on its own; spooling would silently move the
+    // upload out of the queue into the spool directory.
+    let options = UploadOptions {
+        offline_spool: false,
+        ..options.unwrap_or_default()
+    };
+    let options_json = serde_json::to_string(&options).map_err(|e| e.to_string())?;
+    let now = now_ms();
+    conn.execute(
+        "INSERT INTO upload_queue (upload_id, url, payload, options, next_attempt_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+        params![upload_id, url, payload_json, options_json, now],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Drops an upload from the persistent queue, cancelling it if the worker
+/// is sending it right now. Returns whether anything was queued under that id.
+#[tauri::command]
+fn remove_queued_upload(
+    db: tauri::State<DbState>,
+    uploads: tauri::State<UploadState>,
+    upload_id: String,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    let removed = conn
+        .execute("DELETE FROM upload_queue WHERE upload_id = ?1", params![upload_id])
+        .map_err(|e| e.to_string())?;
+    uploads.cancel(&upload_id);
+    Ok(removed > 0)
+}
+
+#[tauri::command]
+fn get_queue(state: tauri::State<DbState>) -> Result<Vec<QueuedUpload>, String> {
+    let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT upload_id, url, attempts, last_error, next_attempt_at, created_at
+             FROM upload_queue ORDER BY created_at ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(QueuedUpload {
+                upload_id: row.get(0)?,
+                url: row.get(1)?,
+                attempts: row.get(2)?,
+                last_error: row.get(3)?,
+                next_attempt_at: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    let mut queued = Vec::new();
+    for row in rows {
+        queued.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(queued)
+}
+
+type QueueRow = (String, String, String, String, i64);
+
+fn next_queued_upload(app: &tauri::AppHandle) -> Result<Option<QueueRow>, String> {
+    let state = app
+        .try_state::<DbState>()
+        .ok_or_else(|| "Database not initialised".to_string())?;
+    let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    conn.query_row(
+        "SELECT upload_id, url, payload, options, attempts FROM upload_queue
+         WHERE next_attempt_at <= ?1 ORDER BY created_at ASC LIMIT 1",
+        params![now_ms()],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Only failures that happened before any of the body reached the server
+// are retried. Anything later (timeouts, 5xx, oversized or unparseable
+// responses) may mean the payment was already recorded, and the upload
+// request carries no idempotency key, so a retry could duplicate it.
+fn is_retryable_upload_error(error: &str) -> bool {
+    error.starts_with(OFFLINE_ERROR_PREFIX)
+}
+
+// Removes the entry on success, on a non-retryable error or once it has used
+// up its attempts; otherwise reschedules it with exponential backoff.
+// Returns whether the entry was removed.
+fn finish_queued_upload(
+    app: &tauri::AppHandle,
+    upload_id: &str,
+    attempts: i64,
+    error: Option<&str>,
+) -> Result<bool, String> {
+    let state = app
+        .try_state::<DbState>()
+        .ok_or_else(|| "Database not initialised".to_string())?;
+    let conn = state.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+    let retry_error = error.filter(|error| {
+        is_retryable_upload_error(error) && attempts + 1 < UPLOAD_QUEUE_MAX_ATTEMPTS
+    });
+    let remove = retry_error.is_none();
+    match retry_error {
+        None => conn.execute("DELETE FROM upload_queue WHERE upload_id = ?1", params![upload_id]),
+        Some(error) => {
+            let backoff = UPLOAD_QUEUE_BASE_BACKOFF_MS
+                .saturating_mul(1i64 << attempts.clamp(0, 16))
+                .min(UPLOAD_QUEUE_MAX_BACKOFF_MS);
+            conn.execute(
+                "UPDATE upload_queue SET attempts = ?2, last_error = ?3, next_attempt_at = ?4
+                 WHERE upload_id = ?1",
+                params![upload_id, attempts + 1, error, now_ms() + backoff],
+            )
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(remove)
+}
+
+// Drains upload_queue one item at a time, so queued uploads never compete
+// with each other for bandwidth. Anything left from a previous run is picked
+// up as soon as the worker starts.
+fn spawn_upload_queue_worker(app: tauri::AppHandle) {
+    thread::spawn(move || loop {
+        let state = app.state::<UploadState>();
+        let next = if state.uploads_enabled() {
+            next_queued_upload(&app)
+        } else {
+            Ok(None)
+        };
+        let (upload_id, url, payload, options, attempts) = match next {
+            Ok(Some(row)) => row,
+            Ok(None) => {
+                thread::sleep(UPLOAD_QUEUE_POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Upload queue error: {}", e);
+                thread::sleep(UPLOAD_QUEUE_POLL_INTERVAL);
+                continue;
+            }
+        };
+        let payload = serde_json::from_str(&payload).unwrap_or(serde_json::Value::Null);
+        let options = UploadOptions {
+            offline_spool: false,
+            ..serde_json::from_str(&options).unwrap_or_default()
+        };
+        let result = perform_upload(&app, &state, url, payload, upload_id.clone(), options);
+        let error = result.err();
+        let removed = finish_queued_upload(&app, &upload_id, attempts, error.as_deref())
+            .unwrap_or_else(|e| {
+                eprintln!("Upload queue error: {}", e);
+                false
+            });
+        let _ = app.emit(
+            "upload-queue-result",
+            UploadQueueEvent {
+                upload_id,
+                ok: error.is_none(),
+                error,
+                removed,
+            },
+        );
+    });
+}
+
 #[tauri::command]
 fn cancel_upload(state: tauri::State<UploadState>, upload_id: String) -> bool {
     state.cancel(&upload_id)
 }
 
+/// Cancels every in-flight upload whose id starts with `prefix` (e.g.
+/// "payment-123-") and returns how many were cancelled. An empty prefix is
+/// rejected rather than cancelling everything.
+#[tauri::command]
+fn cancel_uploads_matching(state: tauri::State<UploadState>, prefix: String) -> Result<usize, String> {
+    if prefix.is_empty() {
+        return Err("Prefix must not be empty".to_string());
+    }
+    Ok(state.cancel_matching(&prefix))
+}
+
+/// Downloads a stored attachment and compares its SHA-256 with that of the
+/// raw attachment bytes. Pass either `sentDataUrl` (the data URL that was
+/// uploaded, hashed here after base64 decoding) or `expectedSha256` (a hex
+/// digest of those raw bytes). Upload results' `bodySha256` hashes the JSON
+/// request envelope and will never match. On a mismatch,
+/// `possiblyTranscoded` is set when the download is still a valid image,
+/// which usually means the server re-encoded it rather than corrupted it.
+#[tauri::command(async)]
+fn verify_uploaded(
+    state: tauri::State<UploadState>,
+    download_url: String,
+    sent_data_url: Option<String>,
+    expected_sha256: Option<String>,
+) -> Result<UploadVerification, String> {
+    if download_url.trim().is_empty() {
+        return Err("Missing download URL".to_string());
+    }
+    let expected_sha256 = match (sent_data_url, expected_sha256) {
+        (Some(data_url), None) => to_hex(&Sha256::digest(imaging::parse_data_url(&data_url)?.1)),
+        (None, Some(digest)) => digest.trim().to_ascii_lowercase(),
+        _ => return Err("Provide exactly one of sentDataUrl or expectedSha256".to_string()),
+    };
+    let agent = state.agent();
+    let response = agent.get(&download_url).call().map_err(|e| e.to_string())?;
+    let allowlist = default_redirect_allowlist(&download_url);
+    let response = follow_redirects(&agent, response, DEFAULT_MAX_REDIRECTS, &allowlist)?;
+    let content_type = response.header("Content-Type").map(str::to_string);
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_VERIFY_DOWNLOAD_BYTES.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() as u64 > MAX_VERIFY_DOWNLOAD_BYTES {
+        return Err(format!("Download exceeded the {} byte limit", MAX_VERIFY_DOWNLOAD_BYTES));
+    }
+    let actual_sha256 = to_hex(&Sha256::digest(&bytes));
+    let verified = actual_sha256 == expected_sha256;
+    Ok(UploadVerification {
+        verified,
+        possibly_transcoded: !verified && image::guess_format(&bytes).is_ok(),
+        actual_sha256,
+        bytes: bytes.len() as u64,
+        content_type,
+    })
+}
+
+#[tauri::command]
+fn configure_tls(state: tauri::State<UploadState>, options: tls::TlsOptions) -> Result<(), String> {
+    if options.is_default() {
+        state.set_agent(None);
+        return Ok(());
+    }
+    let agent = tls::build_agent(&options)?;
+    state.set_agent(Some(agent));
+    Ok(())
+}
+
+#[tauri::command]
+fn set_uploads_enabled(state: tauri::State<UploadState>, enabled: bool) {
+    state.set_uploads_enabled(enabled);
+}
+
+#[tauri::command]
+fn get_upload_throughput_history(state: tauri::State<UploadState>) -> Vec<ThroughputSample> {
+    state.throughput_history()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -357,12 +1388,15 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .manage(UploadState::default())
         .setup(|app| {
-            let conn = setup_db(&app.handle())
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let conn = setup_db(app.handle()).map_err(io::Error::other)?;
             app.manage(DbState { conn: Mutex::new(conn) });
+            spawn_upload_queue_worker(app.handle().clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            check_free_space,
+            data_url_to_temp_file,
+            cleanup_temp_file,
             cache_get,
             cache_set,
             cache_delete,
@@ -372,9 +1406,74 @@ pub fn run() {
             queue_delete,
             queue_clear,
             queue_count,
+            get_data_usage,
             upload_payment_attachment,
-            cancel_upload
+            enqueue_upload,
+            get_queue,
+            remove_queued_upload,
+            cancel_upload,
+            cancel_uploads_matching,
+            verify_uploaded,
+            list_spooled,
+            flush_spool,
+            discard_spooled,
+            configure_tls,
+            set_uploads_enabled,
+            get_upload_throughput_history,
+            imaging::compare_images,
+            imaging::read_exif,
+            imaging::probe_images,
+            imaging::rotate_jpeg_lossless,
+            imaging::compose_images,
+            imaging::resize_image,
+            imaging::generate_placeholder,
+            imaging::auto_trim,
+            imaging::assess_quality,
+            imaging::solve_max_dim_for_budget,
+            imaging::file_to_data_url,
+            imaging::looks_like_document,
+            imaging::compute_resized_dimensions,
+            imaging::validate_data_url,
+            imaging::quantize_image,
+            imaging::dominant_colors,
+            imaging::has_transparency,
+            imaging::benchmark_base64,
+            imaging::extension_for_mime,
+            imaging::overlay_image,
+            imaging::tile_image,
+            imaging::is_safe_image_payload,
+            imaging::binarize_image,
+            imaging::diff_images,
+            imaging::compress_preview,
+            imaging::image_dimensions,
+            imaging::decode_low_res_preview,
+            imaging::decode_region,
+            pdf::images_to_pdf,
+            pdf::append_images_to_pdf
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_date_formats_the_epoch() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn utc_date_handles_leap_days() {
+        assert_eq!(utc_date(1_709_208_000_000), "2024-02-29");
+        assert_eq!(utc_date(1_709_208_000_000 + 86_400_000), "2024-03-01");
+    }
+
+    #[test]
+    fn utc_date_crosses_the_year_boundary() {
+        assert_eq!(utc_date(946_684_799_999), "1999-12-31");
+        assert_eq!(utc_date(946_684_800_000), "2000-01-01");
+    }
+}
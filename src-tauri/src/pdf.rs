@@ -0,0 +1,240 @@
+use crate::imaging::{build_data_url, decode_data_url, parse_data_url};
+use image::codecs::jpeg::JpegEncoder;
+use image::DynamicImage;
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+use serde::Serialize;
+
+// Page sizes are in PDF points (1/72 inch).
+const A4_POINTS: (f32, f32) = (595.0, 842.0);
+const LETTER_POINTS: (f32, f32) = (612.0, 792.0);
+const PAGE_MARGIN_POINTS: f32 = 36.0;
+// Images are downscaled to this density on the page; enough for print and OCR.
+const PAGE_IMAGE_DPI: f32 = 200.0;
+const PAGE_JPEG_QUALITY: u8 = 85;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PdfOutput {
+    bytes: usize,
+    data_url: String,
+}
+
+fn page_size(name: Option<&str>) -> Result<(f32, f32), String> {
+    match name.map(str::to_ascii_lowercase).as_deref() {
+        None | Some("a4") => Ok(A4_POINTS),
+        Some("letter") => Ok(LETTER_POINTS),
+        Some(other) => Err(format!("Unsupported page size: {}", other)),
+    }
+}
+
+// JPEG has no alpha, so transparent areas are flattened onto white (the page
+// colour) rather than left to come out black.
+fn encode_page_jpeg(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let rgb = if image.color().has_alpha() {
+        let rgba = image.to_rgba8();
+        image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let alpha = a as u16;
+            let blend = |c: u8| ((c as u16 * alpha + 255 * (255 - alpha) + 127) / 255) as u8;
+            image::Rgb([blend(r), blend(g), blend(b)])
+        })
+    } else {
+        image.to_rgb8()
+    };
+    let mut out = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(&mut out, PAGE_JPEG_QUALITY);
+    rgb.write_with_encoder(encoder).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+// Adds one page holding `image` fitted inside the margins, preserving its
+// aspect ratio, and returns the new page id. The caller links it into /Kids.
+fn add_image_page(
+    doc: &mut Document,
+    pages_id: ObjectId,
+    image: &DynamicImage,
+    (page_w, page_h): (f32, f32),
+) -> Result<ObjectId, String> {
+    let box_w = page_w - 2.0 * PAGE_MARGIN_POINTS;
+    let box_h = page_h - 2.0 * PAGE_MARGIN_POINTS;
+    let max_px_w = (box_w / 72.0 * PAGE_IMAGE_DPI) as u32;
+    let max_px_h = (box_h / 72.0 * PAGE_IMAGE_DPI) as u32;
+    let image = if image.width() > max_px_w || image.height() > max_px_h {
+        image.resize(max_px_w, max_px_h, image::imageops::FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+    let (px_w, px_h) = (image.width() as f32, image.height() as f32);
+    let scale = (box_w / px_w).min(box_h / px_h);
+    let (draw_w, draw_h) = (px_w * scale, px_h * scale);
+    let x = (page_w - draw_w) / 2.0;
+    let y = (page_h - draw_h) / 2.0;
+
+    let mut image_stream = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => image.width() as i64,
+            "Height" => image.height() as i64,
+            "ColorSpace" => "DeviceRGB",
+            "BitsPerComponent" => 8,
+            "Filter" => "DCTDecode",
+        },
+        encode_page_jpeg(&image)?,
+    );
+    image_stream.allows_compression = false;
+    let image_id = doc.add_object(image_stream);
+
+    let content = Content {
+        operations: vec![
+            Operation::new("q", vec![]),
+            Operation::new(
+                "cm",
+                vec![
+                    draw_w.into(),
+                    0.into(),
+                    0.into(),
+                    draw_h.into(),
+                    x.into(),
+                    y.into(),
+                ],
+            ),
+            Operation::new("Do", vec!["Im0".into()]),
+            Operation::new("Q", vec![]),
+        ],
+    };
+    let content_id = doc.add_object(Stream::new(
+        dictionary! {},
+        content.encode().map_err(|e| e.to_string())?,
+    ));
+    Ok(doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), page_w.into(), page_h.into()],
+        "Contents" => content_id,
+        "Resources" => dictionary! {
+            "XObject" => dictionary! { "Im0" => image_id },
+        },
+    }))
+}
+
+fn save_pdf(doc: &mut Document) -> Result<PdfOutput, String> {
+    let mut out = Vec::new();
+    doc.save_to(&mut out).map_err(|e| e.to_string())?;
+    Ok(PdfOutput {
+        bytes: out.len(),
+        data_url: build_data_url("application/pdf", &out),
+    })
+}
+
+/// Builds a PDF with one image per page, each fitted inside a 0.5" margin.
+/// `page_size` is "A4" (default) or "Letter".
+#[tauri::command(async)]
+pub(crate) fn images_to_pdf(
+    data_urls: Vec<String>,
+    page_size: Option<String>,
+) -> Result<PdfOutput, String> {
+    if data_urls.is_empty() {
+        return Err("No images to place in the PDF".to_string());
+    }
+    let size = self::page_size(page_size.as_deref())?;
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let mut kids = Vec::new();
+    for data_url in &data_urls {
+        let image = decode_data_url(data_url)?;
+        kids.push(Object::from(add_image_page(
+            &mut doc, pages_id, &image, size,
+        )?));
+    }
+    let count = kids.len() as i64;
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+    save_pdf(&mut doc)
+}
+
+fn load_existing_pdf(pdf: &str) -> Result<Document, String> {
+    let bytes = if pdf.trim_start().starts_with("data:") {
+        parse_data_url(pdf)?.1
+    } else {
+        std::fs::read(pdf).map_err(|e| format!("Could not read {}: {}", pdf, e))?
+    };
+    let doc = Document::load_mem(&bytes).map_err(|e| format!("Invalid PDF: {}", e))?;
+    if doc.is_encrypted() {
+        return Err("Invalid PDF: encrypted documents are not supported".to_string());
+    }
+    Ok(doc)
+}
+
+/// Appends one page per image to an existing PDF, given as a data URL or a
+/// file path, and returns the combined document. New pages are added to the
+/// root page tree using the same layout as images_to_pdf.
+#[tauri::command(async)]
+pub(crate) fn append_images_to_pdf(
+    pdf: String,
+    data_urls: Vec<String>,
+    page_size: Option<String>,
+) -> Result<PdfOutput, String> {
+    if data_urls.is_empty() {
+        return Err("No images to append to the PDF".to_string());
+    }
+    let size = self::page_size(page_size.as_deref())?;
+    let mut doc = load_existing_pdf(&pdf)?;
+    let pages_id = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| format!("Invalid PDF: missing page tree ({})", e))?;
+
+    let invalid = |e: lopdf::Error| format!("Invalid PDF: {}", e);
+    let pages = doc
+        .get_object(pages_id)
+        .and_then(Object::as_dict)
+        .map_err(invalid)?;
+    let count = pages
+        .get(b"Count")
+        .and_then(Object::as_i64)
+        .map_err(invalid)?;
+    // /Kids may be stored inline or as an indirect array object.
+    let kids_ref = match pages.get(b"Kids").map_err(invalid)? {
+        Object::Array(_) => None,
+        Object::Reference(id) => Some(*id),
+        _ => return Err("Invalid PDF: page tree /Kids is not an array".to_string()),
+    };
+
+    let mut new_pages = Vec::new();
+    for data_url in &data_urls {
+        let image = decode_data_url(data_url)?;
+        new_pages.push(Object::from(add_image_page(
+            &mut doc, pages_id, &image, size,
+        )?));
+    }
+    let added = new_pages.len() as i64;
+    let kids = match kids_ref {
+        Some(id) => doc.get_object_mut(id),
+        None => doc
+            .get_object_mut(pages_id)
+            .and_then(Object::as_dict_mut)
+            .and_then(|pages| pages.get_mut(b"Kids")),
+    }
+    .and_then(Object::as_array_mut)
+    .map_err(invalid)?;
+    kids.extend(new_pages);
+    doc.get_object_mut(pages_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(invalid)?
+        .set("Count", count + added);
+    save_pdf(&mut doc)
+}
@@ -0,0 +1,139 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct TlsOptions {
+    /// PEM-encoded root certificate(s) trusted in addition to the public roots.
+    root_cert_pem: Option<String>,
+    /// Path to a PEM file with extra root certificate(s).
+    root_cert_pem_path: Option<String>,
+    /// Skips certificate verification entirely. Only honoured in debug builds,
+    /// for local development against self-signed endpoints; release builds
+    /// reject it.
+    danger_accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+    pub(crate) fn is_default(&self) -> bool {
+        self.root_cert_pem.is_none()
+            && self.root_cert_pem_path.is_none()
+            && !self.danger_accept_invalid_certs
+    }
+}
+
+#[derive(Debug)]
+struct AcceptAnyServerCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyServerCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn load_root_certs(options: &TlsOptions) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let mut pems = Vec::new();
+    if let Some(pem) = &options.root_cert_pem {
+        pems.push(pem.as_bytes().to_vec());
+    }
+    if let Some(path) = &options.root_cert_pem_path {
+        pems.push(std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?);
+    }
+    for pem in pems {
+        let mut added = 0;
+        for cert in CertificateDer::pem_slice_iter(&pem) {
+            let cert = cert.map_err(|e| e.to_string())?;
+            roots.add(cert).map_err(|e| e.to_string())?;
+            added += 1;
+        }
+        if added == 0 {
+            return Err("No certificates found in root certificate PEM".to_string());
+        }
+    }
+    Ok(roots)
+}
+
+/// Builds an HTTP agent whose TLS config trusts the public web roots plus any
+/// custom roots supplied, or that skips verification when the danger flag is
+/// set in a debug build.
+pub(crate) fn build_agent(options: &TlsOptions) -> Result<ureq::Agent, String> {
+    if options.danger_accept_invalid_certs {
+        if !cfg!(debug_assertions) {
+            return Err(
+                "dangerAcceptInvalidCerts is only available in development builds".to_string(),
+            );
+        }
+        if options.root_cert_pem.is_some() || options.root_cert_pem_path.is_some() {
+            return Err(
+                "dangerAcceptInvalidCerts cannot be combined with custom root certificates"
+                    .to_string(),
+            );
+        }
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS12, &rustls::version::TLS13])
+        .map_err(|e| e.to_string())?;
+    let config = if options.danger_accept_invalid_certs {
+        eprintln!("WARNING: TLS certificate verification is disabled for uploads");
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyServerCert(provider)))
+            .with_no_client_auth()
+    } else {
+        builder
+            .with_root_certificates(load_root_certs(options)?)
+            .with_no_client_auth()
+    };
+    Ok(ureq::AgentBuilder::new()
+        .tls_config(Arc::new(config))
+        .redirects(0)
+        .build())
+}